@@ -0,0 +1,216 @@
+use crate::{
+    filter::{is_hidden, ContentFilter, FilterOptions},
+    markdown::MarkdownOptions,
+};
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Checks whether a path relative to the content directory is a static asset,
+/// i.e. a non-markdown file that should be mirrored into the output directory as-is.
+///
+/// Hidden files and anything inside hidden directories (`.git`, `.DS_Store`) are skipped.
+pub fn is_asset(rel_path: &Path, options: &MarkdownOptions) -> bool {
+    !options.is_markdown(rel_path) && !rel_path.components().any(|c| is_hidden(c.as_os_str()))
+}
+
+/// Collects the asset files the filter includes, skipping `exclude_dir`
+/// (typically the output directory when it lives inside the content directory)
+pub fn collect_asset_files(
+    filter: &ContentFilter,
+    options: &MarkdownOptions,
+    exclude_dir: &Path,
+    files: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let exclude_dir = exclude_dir.canonicalize().ok();
+    let content_dir = filter.content_dir();
+    for path in filter.files()? {
+        let rel_path = path.strip_prefix(content_dir).unwrap_or(&path);
+        if !is_asset(rel_path, options) {
+            continue;
+        }
+        let in_excluded_dir = exclude_dir.as_deref().is_some_and(|exclude_dir| {
+            path.parent()
+                .and_then(|parent| parent.canonicalize().ok())
+                .is_some_and(|parent| parent.starts_with(exclude_dir))
+        });
+        if !in_excluded_dir {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Determines where an asset is mirrored to in the output directory
+pub fn get_asset_output_path(
+    asset_path: &Path,
+    content_dir: &Path,
+    output_dir: &Path,
+) -> Result<PathBuf> {
+    let rel_path = asset_path.strip_prefix(content_dir).with_context(|| {
+        format!(
+            "Asset is outside the content directory: {}",
+            asset_path.display()
+        )
+    })?;
+    Ok(output_dir.join(rel_path))
+}
+
+/// Copies an asset into the output directory, preserving its relative path
+pub fn copy_asset(asset_path: &Path, content_dir: &Path, output_dir: &Path) -> Result<PathBuf> {
+    let output_path = get_asset_output_path(asset_path, content_dir, output_dir)?;
+
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+
+    fs::copy(asset_path, &output_path)
+        .with_context(|| format!("Failed to copy asset: {}", asset_path.display()))?;
+
+    Ok(output_path)
+}
+
+/// Removes the mirrored copy of an asset that no longer exists in the content directory
+pub fn remove_asset(asset_path: &Path, content_dir: &Path, output_dir: &Path) -> Result<()> {
+    let output_path = get_asset_output_path(asset_path, content_dir, output_dir)?;
+    // A removed directory that looked like an asset; its files are handled one by one
+    if output_path.is_dir() {
+        return Ok(());
+    }
+
+    match fs::remove_file(&output_path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => {
+            Err(e).with_context(|| format!("Failed to remove asset: {}", output_path.display()))
+        }
+    }
+}
+
+/// Brings the mirrored copy of an asset in line with the content directory:
+/// copies it if the asset exists, removes the copy if it was deleted or renamed away
+pub fn sync_asset(asset_path: &Path, content_dir: &Path, output_dir: &Path) -> Result<()> {
+    if asset_path.is_file() {
+        copy_asset(asset_path, content_dir, output_dir)?;
+    } else if !asset_path.exists() {
+        remove_asset(asset_path, content_dir, output_dir)?;
+    }
+    Ok(())
+}
+
+/// Copies every asset in the content directory into the output directory
+pub fn copy_all_assets(
+    content_dir: &Path,
+    output_dir: &Path,
+    options: &MarkdownOptions,
+    filter: &FilterOptions,
+) -> Result<Vec<PathBuf>> {
+    let filter = ContentFilter::new(content_dir, filter)?;
+    let mut assets = Vec::new();
+    collect_asset_files(&filter, options, output_dir, &mut assets)?;
+
+    for path in &assets {
+        copy_asset(path, content_dir, output_dir)?;
+    }
+
+    Ok(assets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_is_asset() {
+        let is_asset = |path: &str| is_asset(Path::new(path), &MarkdownOptions::default());
+        assert!(is_asset("images/logo.png"));
+        assert!(is_asset("style.css"));
+        assert!(is_asset("LICENSE"));
+        assert!(!is_asset("page.md"));
+        assert!(!is_asset("notes.Markdown"));
+        assert!(!is_asset(".DS_Store"));
+        assert!(!is_asset(".git/HEAD"));
+    }
+
+    #[test]
+    fn test_copy_all_assets() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content_dir = temp_dir.path().join("content");
+        let output_dir = temp_dir.path().join("output");
+
+        fs::create_dir_all(content_dir.join("images"))?;
+        fs::create_dir_all(content_dir.join(".git"))?;
+        fs::write(content_dir.join("page.md"), "# Page")?;
+        fs::write(content_dir.join("images").join("logo.svg"), "<svg/>")?;
+        fs::write(content_dir.join("spec.pdf"), "%PDF")?;
+        fs::write(content_dir.join(".git").join("HEAD"), "ref")?;
+
+        let assets = copy_all_assets(
+            &content_dir,
+            &output_dir,
+            &MarkdownOptions::default(),
+            &FilterOptions::default(),
+        )?;
+
+        assert_eq!(assets.len(), 2);
+        assert_eq!(
+            fs::read_to_string(output_dir.join("images").join("logo.svg"))?,
+            "<svg/>"
+        );
+        assert!(output_dir.join("spec.pdf").exists());
+        assert!(!output_dir.join("page.md").exists());
+        assert!(!output_dir.join(".git").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_dir_inside_content_dir_is_skipped() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content_dir = temp_dir.path();
+        let output_dir = content_dir.join("_dist");
+
+        fs::create_dir_all(&output_dir)?;
+        fs::write(content_dir.join("logo.png"), "png")?;
+        fs::write(output_dir.join("page.html"), "<html>")?;
+
+        let filter = ContentFilter::new(content_dir, &FilterOptions::default())?;
+        let mut assets = Vec::new();
+        collect_asset_files(
+            &filter,
+            &MarkdownOptions::default(),
+            &output_dir,
+            &mut assets,
+        )?;
+
+        assert_eq!(assets, vec![content_dir.join("logo.png")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_asset() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content_dir = temp_dir.path().join("content");
+        let output_dir = temp_dir.path().join("output");
+        fs::create_dir_all(&content_dir)?;
+
+        let asset = content_dir.join("diagram.svg");
+        fs::write(&asset, "<svg/>")?;
+        let copied = copy_asset(&asset, &content_dir, &output_dir)?;
+        assert!(copied.exists());
+
+        fs::remove_file(&asset)?;
+        remove_asset(&asset, &content_dir, &output_dir)?;
+        assert!(!copied.exists());
+
+        // Removing an asset that was never copied is not an error
+        remove_asset(&asset, &content_dir, &output_dir)?;
+
+        Ok(())
+    }
+}
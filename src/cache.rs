@@ -0,0 +1,251 @@
+use crate::markdown::{last_modified, PageContent};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// File in the output directory where the server keeps its render cache between runs
+pub const CACHE_FILE: &str = ".live-md-cache.json";
+
+/// Version of the rendered output. Bump this whenever a change to rendering
+/// makes pages rendered by earlier builds stale; output cached under another
+/// version is never reused.
+const RENDERER_VERSION: u32 = 1;
+
+/// Hashes a sequence of byte strings with 64-bit FNV-1a.
+///
+/// Each part is prefixed with its length so that moving bytes between parts
+/// changes the hash. The result is stable across runs and toolchains, unlike
+/// `std::hash::DefaultHasher`.
+pub fn fingerprint<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> String {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    let mut hash = OFFSET_BASIS;
+    for part in parts {
+        for byte in (part.len() as u64).to_le_bytes().iter().chain(part) {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(PRIME);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+/// What a rendered page was built from, and when its output was written
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CacheEntry {
+    /// Fingerprint of the page's source
+    inputs: String,
+
+    /// Fingerprint of the site navigation the page was laid out with
+    nav: String,
+
+    /// The rendered markdown, laid out again when only the navigation changes
+    content: PageContent,
+
+    /// Modification time of the output file, so edits to it aren't kept
+    output_modified: SystemTime,
+}
+
+/// How much of a page's last render can be reused
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reuse<'a> {
+    /// The output file is up to date
+    Output,
+
+    /// The markdown is unchanged but the page needs laying out again, such
+    /// as when the navigation changed
+    Content(&'a PageContent),
+
+    /// The page needs to be rendered from its source
+    Nothing,
+}
+
+/// Record of the pages rendered into an output directory.
+///
+/// A page is up to date when its source, the site navigation and the page
+/// template are unchanged since it was last rendered, and its output file
+/// hasn't been touched since. When only the navigation changed, the page's
+/// rendered markdown is kept to lay out again. The whole cache is discarded
+/// when the renderer version or template changes.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RenderCache {
+    renderer: u32,
+    template: String,
+    pages: BTreeMap<PathBuf, CacheEntry>,
+}
+
+impl RenderCache {
+    /// Loads the cache from the output directory for the given template
+    /// fingerprint. A missing, unreadable or outdated cache starts empty.
+    pub fn load(output_dir: &Path, template: &str) -> Self {
+        let empty = Self {
+            renderer: RENDERER_VERSION,
+            template: template.to_string(),
+            pages: BTreeMap::new(),
+        };
+        let Ok(json) = fs::read_to_string(output_dir.join(CACHE_FILE)) else {
+            return empty;
+        };
+        match serde_json::from_str::<Self>(&json) {
+            Ok(cache) if cache.renderer == empty.renderer && cache.template == template => cache,
+            _ => empty,
+        }
+    }
+
+    /// Deletes a cache left in the output directory, if there is one
+    pub fn remove(output_dir: &Path) -> Result<()> {
+        let path = output_dir.join(CACHE_FILE);
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove render cache: {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes the cache to the output directory
+    pub fn save(&self, output_dir: &Path) -> Result<()> {
+        let path = output_dir.join(CACHE_FILE);
+        let json = serde_json::to_string(self)?;
+        fs::write(&path, json)
+            .with_context(|| format!("Failed to write render cache: {}", path.display()))
+    }
+
+    /// Works out how much of the render of `source` to `output` can be
+    /// reused for the given inputs and navigation
+    pub fn reuse(&self, source: &Path, inputs: &str, nav: &str, output: &Path) -> Reuse<'_> {
+        match self.pages.get(source) {
+            Some(entry) if entry.inputs == inputs => {
+                let output_kept = last_modified(output).is_some_and(|t| t == entry.output_modified);
+                if entry.nav == nav && output_kept {
+                    Reuse::Output
+                } else {
+                    Reuse::Content(&entry.content)
+                }
+            }
+            _ => Reuse::Nothing,
+        }
+    }
+
+    /// Records that `output` was just laid out from `content`, rendered
+    /// from the given inputs, with the given navigation
+    pub fn insert(
+        &mut self,
+        source: &Path,
+        inputs: String,
+        nav: &str,
+        content: PageContent,
+        output: &Path,
+    ) {
+        if let Some(output_modified) = last_modified(output) {
+            self.pages.insert(
+                source.to_path_buf(),
+                CacheEntry {
+                    inputs,
+                    nav: nav.to_string(),
+                    content,
+                    output_modified,
+                },
+            );
+        }
+    }
+
+    /// Drops a page, so it is rendered again next time
+    pub fn forget(&mut self, source: &Path) {
+        self.pages.remove(source);
+    }
+
+    /// Drops the pages whose source isn't kept
+    pub fn retain(&mut self, mut keep: impl FnMut(&Path) -> bool) {
+        self.pages.retain(|source, _| keep(source));
+    }
+
+    /// Number of pages in the cache
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Checks whether the cache has no pages
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_fingerprint() {
+        let hash = fingerprint([b"hello".as_slice()]);
+        assert_eq!(hash.len(), 16);
+        assert_eq!(hash, fingerprint([b"hello".as_slice()]));
+        assert_ne!(hash, fingerprint([b"hellp".as_slice()]));
+        assert_ne!(
+            fingerprint([b"ab".as_slice(), b"c"]),
+            fingerprint([b"a".as_slice(), b"bc"])
+        );
+    }
+
+    #[test]
+    fn test_cache_round_trip() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let output_dir = temp_dir.path();
+        let source = Path::new("page.md");
+        let output = output_dir.join("page.html");
+        fs::write(&output, "<html>")?;
+
+        let content = PageContent {
+            title: "Page".to_string(),
+            html: "<p>Page</p>".to_string(),
+            toc: String::new(),
+            meta: Default::default(),
+            last_modified: None,
+        };
+
+        let mut cache = RenderCache::load(output_dir, "template");
+        assert!(cache.is_empty());
+        cache.insert(
+            source,
+            "inputs".to_string(),
+            "nav",
+            content.clone(),
+            &output,
+        );
+        assert_eq!(cache.reuse(source, "inputs", "nav", &output), Reuse::Output);
+        assert_eq!(
+            cache.reuse(source, "changed", "nav", &output),
+            Reuse::Nothing
+        );
+        cache.save(output_dir)?;
+
+        let cache = RenderCache::load(output_dir, "template");
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.reuse(source, "inputs", "nav", &output), Reuse::Output);
+
+        // New navigation only needs the content laid out again
+        assert_eq!(
+            cache.reuse(source, "inputs", "other nav", &output),
+            Reuse::Content(&content)
+        );
+
+        // Outputs that are gone, or rendered with another template, are stale
+        assert!(RenderCache::load(output_dir, "other").is_empty());
+        fs::remove_file(&output)?;
+        assert_eq!(
+            cache.reuse(source, "inputs", "nav", &output),
+            Reuse::Content(&content)
+        );
+
+        // A corrupt cache is ignored
+        fs::write(output_dir.join(CACHE_FILE), "{")?;
+        assert!(RenderCache::load(output_dir, "template").is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,516 @@
+use crate::config::Config;
+use anyhow::Result;
+use clap::{Args, Parser, Subcommand};
+use std::{net::IpAddr, path::PathBuf};
+
+/// Command line interface for the live-md binary
+#[derive(Debug, Parser)]
+#[command(name = "live-md", version, about, long_about = None)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub options: ConfigArgs,
+}
+
+/// The action to perform. Defaults to `serve` when omitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Subcommand)]
+pub enum Command {
+    /// Render the content directory and serve it with live reload
+    Serve,
+    /// Render the content directory once as a static site and exit
+    Build,
+}
+
+/// Options that map onto fields of `Config`.
+///
+/// These take precedence over the config file and environment variables.
+#[derive(Debug, Default, Args)]
+pub struct ConfigArgs {
+    /// Read configuration from FILE instead of searching for live-md.toml
+    #[arg(long, value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// The directory containing markdown files
+    #[arg(short, long, value_name = "DIR", global = true)]
+    pub content_dir: Option<PathBuf>,
+
+    /// The directory where HTML files will be generated
+    #[arg(short, long, value_name = "DIR", global = true)]
+    pub output_dir: Option<PathBuf>,
+
+    /// The IP address to bind to
+    #[arg(long, global = true)]
+    pub host: Option<IpAddr>,
+
+    /// The port to run the server on
+    #[arg(short, long, global = true)]
+    pub port: Option<u16>,
+
+    /// Open a browser when the server starts
+    #[arg(long, overrides_with = "no_open", global = true)]
+    pub open: bool,
+
+    /// Don't open a browser when the server starts
+    #[arg(long, overrides_with = "open", global = true)]
+    pub no_open: bool,
+
+    /// The number of events to buffer in the broadcast channel
+    #[arg(long, value_name = "N", global = true)]
+    pub broadcast_capacity: Option<usize>,
+
+    /// Render pages on request instead of writing them to the output directory
+    #[arg(long, overrides_with = "no_in_memory", global = true)]
+    pub in_memory: bool,
+
+    /// Write pages to the output directory instead of rendering them on request
+    #[arg(long, overrides_with = "in_memory", global = true)]
+    pub no_in_memory: bool,
+
+    /// Milliseconds to wait for file changes to settle before rendering
+    #[arg(long, value_name = "MS", global = true)]
+    pub debounce_ms: Option<u64>,
+
+    /// Only include files matching GLOB, relative to the content directory (repeatable)
+    #[arg(long, value_name = "GLOB", global = true)]
+    pub include: Vec<String>,
+
+    /// Skip files and directories matching GLOB, in .gitignore syntax (repeatable)
+    #[arg(long, value_name = "GLOB", global = true)]
+    pub exclude: Vec<String>,
+
+    /// Skip node_modules/ and target/ directories
+    #[arg(long, overrides_with = "no_default_excludes", global = true)]
+    pub default_excludes: bool,
+
+    /// Don't skip node_modules/ and target/ unless excluded explicitly
+    #[arg(long, overrides_with = "default_excludes", global = true)]
+    pub no_default_excludes: bool,
+
+    /// Skip files listed in .gitignore and .ignore files
+    #[arg(long, overrides_with = "no_ignore_files", global = true)]
+    pub ignore_files: bool,
+
+    /// Don't skip files listed in .gitignore and .ignore files
+    #[arg(long, overrides_with = "ignore_files", global = true)]
+    pub no_ignore_files: bool,
+
+    /// Override the built-in page and index templates with those in DIR
+    #[arg(long, value_name = "DIR", global = true)]
+    pub templates_dir: Option<PathBuf>,
+
+    /// Treat files with extension EXT as markdown pages, replacing the defaults (repeatable)
+    #[arg(long = "extension", value_name = "EXT", global = true)]
+    pub extensions: Vec<String>,
+
+    /// Enable table rendering
+    #[arg(long, overrides_with = "no_tables", global = true)]
+    pub tables: bool,
+
+    /// Disable table rendering
+    #[arg(long, overrides_with = "tables", global = true)]
+    pub no_tables: bool,
+
+    /// Enable footnote rendering
+    #[arg(long, overrides_with = "no_footnotes", global = true)]
+    pub footnotes: bool,
+
+    /// Disable footnote rendering
+    #[arg(long, overrides_with = "footnotes", global = true)]
+    pub no_footnotes: bool,
+
+    /// Enable strikethrough rendering
+    #[arg(long, overrides_with = "no_strikethrough", global = true)]
+    pub strikethrough: bool,
+
+    /// Disable strikethrough rendering
+    #[arg(long, overrides_with = "strikethrough", global = true)]
+    pub no_strikethrough: bool,
+
+    /// Enable task list rendering
+    #[arg(long, overrides_with = "no_tasklists", global = true)]
+    pub tasklists: bool,
+
+    /// Disable task list rendering
+    #[arg(long, overrides_with = "tasklists", global = true)]
+    pub no_tasklists: bool,
+
+    /// Enable smart quotes and dashes
+    #[arg(long, overrides_with = "no_smart_punctuation", global = true)]
+    pub smart_punctuation: bool,
+
+    /// Disable smart quotes and dashes
+    #[arg(long, overrides_with = "smart_punctuation", global = true)]
+    pub no_smart_punctuation: bool,
+
+    /// Enable syntax highlighting of fenced code blocks
+    #[arg(long, overrides_with = "no_highlight", global = true)]
+    pub highlight: bool,
+
+    /// Disable syntax highlighting of fenced code blocks
+    #[arg(long, overrides_with = "highlight", global = true)]
+    pub no_highlight: bool,
+
+    /// Number the lines of highlighted code blocks
+    #[arg(long, overrides_with = "no_line_numbers", global = true)]
+    pub line_numbers: bool,
+
+    /// Don't number the lines of highlighted code blocks
+    #[arg(long, overrides_with = "line_numbers", global = true)]
+    pub no_line_numbers: bool,
+
+    /// The code highlighting theme for light mode
+    #[arg(long, value_name = "THEME", global = true)]
+    pub highlight_theme_light: Option<String>,
+
+    /// The code highlighting theme for dark mode
+    #[arg(long, value_name = "THEME", global = true)]
+    pub highlight_theme_dark: Option<String>,
+}
+
+impl Cli {
+    /// Returns the requested command, falling back to `serve`
+    pub fn command(&self) -> Command {
+        self.command.unwrap_or(Command::Serve)
+    }
+
+    /// Builds a validated `Config` from the config file, environment and
+    /// the parsed arguments
+    pub fn to_config(&self) -> Result<Config> {
+        self.to_config_with_env(std::env::vars())
+    }
+
+    /// Builds a validated `Config` like [`Cli::to_config`], reading
+    /// environment overrides from `vars` instead of the process environment
+    pub fn to_config_with_env<I>(&self, vars: I) -> Result<Config>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let config = self
+            .options
+            .apply(Config::load_with_env(self.options.config.as_deref(), vars)?);
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+impl ConfigArgs {
+    /// Overrides the fields of `config` with any options given on the command line
+    pub fn apply(&self, mut config: Config) -> Config {
+        if let Some(content_dir) = &self.content_dir {
+            config.content_dir = content_dir.clone();
+        }
+        if let Some(output_dir) = &self.output_dir {
+            config.output_dir = output_dir.clone();
+        }
+        if let Some(host) = self.host {
+            config.host = host;
+        }
+        if let Some(port) = self.port {
+            config.port = port;
+        }
+        if let Some(open) = flag(self.open, self.no_open) {
+            config.open_browser = open;
+        }
+        if let Some(capacity) = self.broadcast_capacity {
+            config.broadcast_capacity = capacity;
+        }
+        if let Some(in_memory) = flag(self.in_memory, self.no_in_memory) {
+            config.in_memory = in_memory;
+        }
+        if let Some(debounce_ms) = self.debounce_ms {
+            config.debounce_ms = debounce_ms;
+        }
+        if let Some(templates_dir) = &self.templates_dir {
+            config.templates_dir = Some(templates_dir.clone());
+        }
+        let filter = &mut config.filter;
+        filter.include.extend(self.include.iter().cloned());
+        filter.exclude.extend(self.exclude.iter().cloned());
+        if let Some(enabled) = flag(self.default_excludes, self.no_default_excludes) {
+            filter.use_default_excludes = enabled;
+        }
+        if let Some(enabled) = flag(self.ignore_files, self.no_ignore_files) {
+            filter.use_ignore_files = enabled;
+        }
+        let markdown = &mut config.markdown;
+        if !self.extensions.is_empty() {
+            markdown.extensions = self.extensions.clone();
+        }
+        for (disabled, enable, disable) in [
+            (&mut markdown.disable_tables, self.tables, self.no_tables),
+            (
+                &mut markdown.disable_footnotes,
+                self.footnotes,
+                self.no_footnotes,
+            ),
+            (
+                &mut markdown.disable_strikethrough,
+                self.strikethrough,
+                self.no_strikethrough,
+            ),
+            (
+                &mut markdown.disable_tasklists,
+                self.tasklists,
+                self.no_tasklists,
+            ),
+            (
+                &mut markdown.disable_smart_punctuation,
+                self.smart_punctuation,
+                self.no_smart_punctuation,
+            ),
+            (
+                &mut markdown.disable_highlighting,
+                self.highlight,
+                self.no_highlight,
+            ),
+        ] {
+            if let Some(enabled) = flag(enable, disable) {
+                *disabled = !enabled;
+            }
+        }
+        if let Some(enabled) = flag(self.line_numbers, self.no_line_numbers) {
+            markdown.line_numbers = enabled;
+        }
+        if let Some(theme) = &self.highlight_theme_light {
+            markdown.highlight_theme_light = Some(theme.clone());
+        }
+        if let Some(theme) = &self.highlight_theme_dark {
+            markdown.highlight_theme_dark = Some(theme.clone());
+        }
+        config
+    }
+}
+
+/// Reads a `--flag`/`--no-flag` pair, giving `None` when neither was passed
+/// so that the config file and environment keep their value
+fn flag(enable: bool, disable: bool) -> Option<bool> {
+    match (enable, disable) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CONFIG_FILE_NAME;
+    use std::{fs, net::Ipv4Addr};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_default_command_is_serve() {
+        let cli = Cli::try_parse_from(["live-md"]).unwrap();
+        assert_eq!(cli.command(), Command::Serve);
+
+        let cli = Cli::try_parse_from(["live-md", "build"]).unwrap();
+        assert_eq!(cli.command(), Command::Build);
+    }
+
+    #[test]
+    fn test_options_override_defaults() {
+        let cli = Cli::try_parse_from([
+            "live-md",
+            "serve",
+            "--content-dir",
+            "notes",
+            "-o",
+            "site",
+            "--host",
+            "0.0.0.0",
+            "-p",
+            "8080",
+            "--no-open",
+            "--broadcast-capacity",
+            "64",
+            "--in-memory",
+            "--debounce-ms",
+            "20",
+            "--templates-dir",
+            "layouts",
+        ])
+        .unwrap();
+
+        let config = cli.options.apply(Config::default());
+        assert_eq!(config.content_dir, PathBuf::from("notes"));
+        assert_eq!(config.output_dir, PathBuf::from("site"));
+        assert_eq!(config.host, IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)));
+        assert_eq!(config.port, 8080);
+        assert!(!config.open_browser);
+        assert_eq!(config.broadcast_capacity, 64);
+        assert!(config.in_memory);
+        assert_eq!(config.debounce_ms, 20);
+        assert_eq!(config.templates_dir, Some(PathBuf::from("layouts")));
+    }
+
+    #[test]
+    fn test_markdown_flags() {
+        let cli =
+            Cli::try_parse_from(["live-md", "build", "--no-tables", "--no-smart-punctuation"])
+                .unwrap();
+
+        let config = cli.options.apply(Config::default());
+        assert!(config.markdown.disable_tables);
+        assert!(config.markdown.disable_smart_punctuation);
+        assert!(!config.markdown.disable_footnotes);
+
+        let cli = Cli::try_parse_from([
+            "live-md",
+            "--no-highlight",
+            "--line-numbers",
+            "--highlight-theme-dark",
+            "Solarized (dark)",
+        ])
+        .unwrap();
+
+        let config = cli.options.apply(Config::default());
+        assert!(config.markdown.disable_highlighting);
+        assert!(config.markdown.line_numbers);
+        assert_eq!(config.markdown.dark_theme(), "Solarized (dark)");
+        assert_eq!(config.markdown.light_theme(), "InspiredGitHub");
+
+        let cli =
+            Cli::try_parse_from(["live-md", "--extension", "md", "--extension", "txt"]).unwrap();
+        let config = cli.options.apply(Config::default());
+        assert_eq!(config.markdown.extensions, ["md", "txt"]);
+    }
+
+    #[test]
+    fn test_filter_flags() {
+        let cli = Cli::try_parse_from([
+            "live-md",
+            "--exclude",
+            "drafts/",
+            "--exclude",
+            "*.tmp",
+            "--include",
+            "*.md",
+            "--no-ignore-files",
+        ])
+        .unwrap();
+
+        // Excludes add to the defaults rather than replacing them
+        let config = cli.options.apply(Config::default());
+        assert_eq!(config.filter.exclude, ["drafts/", "*.tmp"]);
+        assert!(config.filter.use_default_excludes);
+        assert_eq!(config.filter.include, ["*.md"]);
+        assert!(!config.filter.use_ignore_files);
+
+        let cli = Cli::try_parse_from(["live-md", "--no-default-excludes"]).unwrap();
+        let config = cli.options.apply(Config::default());
+        assert!(!config.filter.use_default_excludes);
+    }
+
+    #[test]
+    fn test_flags_turn_settings_back_on() {
+        let mut config = Config {
+            open_browser: false,
+            in_memory: true,
+            ..Config::default()
+        };
+        config.filter.use_ignore_files = false;
+        config.markdown.disable_tables = true;
+        config.markdown.disable_highlighting = true;
+        config.markdown.line_numbers = true;
+
+        let cli = Cli::try_parse_from([
+            "live-md",
+            "--open",
+            "--no-in-memory",
+            "--ignore-files",
+            "--tables",
+            "--highlight",
+            "--no-line-numbers",
+        ])
+        .unwrap();
+        let updated = cli.options.apply(config.clone());
+        assert!(updated.open_browser);
+        assert!(!updated.in_memory);
+        assert!(updated.filter.use_ignore_files);
+        assert!(!updated.markdown.disable_tables);
+        assert!(!updated.markdown.disable_highlighting);
+        assert!(!updated.markdown.line_numbers);
+
+        // Settings without a flag keep their configured value
+        let cli = Cli::try_parse_from(["live-md"]).unwrap();
+        let unchanged = cli.options.apply(config);
+        assert!(!unchanged.open_browser);
+        assert!(unchanged.markdown.disable_tables);
+        assert!(unchanged.markdown.line_numbers);
+
+        // The last of a flag pair wins
+        let cli = Cli::try_parse_from(["live-md", "--no-tables", "--tables"]).unwrap();
+        assert!(!cli.options.apply(Config::default()).markdown.disable_tables);
+    }
+
+    #[test]
+    fn test_flags_override_config_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("custom.toml");
+        fs::write(
+            &config_path,
+            "content_dir = \".\"\nport = 4000\nhost = \"0.0.0.0\"\n",
+        )?;
+
+        let cli = Cli::try_parse_from([
+            "live-md",
+            "--config",
+            config_path.to_str().unwrap(),
+            "--port",
+            "5000",
+        ])?;
+        let config = cli.to_config_with_env(std::iter::empty())?;
+
+        assert_eq!(config.port, 5000);
+        assert_eq!(config.host, IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)));
+        assert_eq!(config.content_dir, temp_dir.path().join("."));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_options_before_subcommand() {
+        let cli = Cli::try_parse_from(["live-md", "--port", "4000", "build"]).unwrap();
+        assert_eq!(cli.command(), Command::Build);
+        assert_eq!(cli.options.port, Some(4000));
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        assert!(Cli::try_parse_from(["live-md", "--port", "not-a-port"]).is_err());
+        assert!(Cli::try_parse_from(["live-md", "--host", "localhost:80"]).is_err());
+        assert!(Cli::try_parse_from(["live-md", "publish"]).is_err());
+    }
+
+    #[test]
+    fn test_to_config_rejects_missing_content_dir() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let missing = temp_dir.path().join("missing");
+        let config_path = temp_dir.path().join(CONFIG_FILE_NAME);
+        fs::write(&config_path, "")?;
+        let config = config_path.to_str().unwrap();
+
+        let cli = Cli::try_parse_from([
+            "live-md",
+            "--config",
+            config,
+            "-c",
+            missing.to_str().unwrap(),
+        ])?;
+        let err = cli.to_config_with_env(std::iter::empty()).unwrap_err();
+        assert!(err.to_string().contains("does not exist"));
+
+        let cli = Cli::try_parse_from([
+            "live-md",
+            "--config",
+            config,
+            "-c",
+            temp_dir.path().to_str().unwrap(),
+        ])?;
+        assert!(cli.to_config_with_env(std::iter::empty()).is_ok());
+
+        Ok(())
+    }
+}
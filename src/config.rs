@@ -1,5 +1,24 @@
+use crate::{
+    filter::{ContentFilter, FilterOptions},
+    highlight,
+    markdown::MarkdownOptions,
+    templates::Templates,
+};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Name of the project-level configuration file
+pub const CONFIG_FILE_NAME: &str = "live-md.toml";
+
+/// Prefix for environment variables that override configuration values
+pub const ENV_PREFIX: &str = "LIVE_MD_";
+
+/// Default time to wait for file changes to settle, long enough to cover
+/// editors that save through a temporary file and a rename
+pub const DEFAULT_DEBOUNCE_MS: u64 = 100;
 
 /// Configuration for the live-md server
 #[derive(Debug, Clone)]
@@ -21,6 +40,76 @@ pub struct Config {
 
     /// The number of events to buffer in the broadcast channel
     pub broadcast_capacity: usize,
+
+    /// Whether the server renders pages on request instead of writing them
+    /// to the output directory
+    pub in_memory: bool,
+
+    /// How long the watcher waits for a burst of file changes to settle
+    /// before rendering, in milliseconds
+    pub debounce_ms: u64,
+
+    /// Which files in the content directory are part of the site
+    pub filter: FilterOptions,
+
+    /// Options controlling markdown rendering
+    pub markdown: MarkdownOptions,
+
+    /// Directory of templates overriding the built-in page and index layouts
+    pub templates_dir: Option<PathBuf>,
+
+    /// Values available to templates as `site`, such as the site title
+    pub site: toml::Table,
+}
+
+/// Configuration as read from a `live-md.toml` file.
+///
+/// Every field is optional so that a file only needs to mention the
+/// settings it wants to change.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    pub content_dir: Option<PathBuf>,
+    pub output_dir: Option<PathBuf>,
+    pub port: Option<u16>,
+    pub host: Option<IpAddr>,
+    pub open_browser: Option<bool>,
+    pub broadcast_capacity: Option<usize>,
+    pub in_memory: Option<bool>,
+    pub debounce_ms: Option<u64>,
+    pub filter: Option<FilterOptions>,
+    pub markdown: Option<MarkdownOptions>,
+    pub templates_dir: Option<PathBuf>,
+    pub site: Option<toml::Table>,
+}
+
+impl FileConfig {
+    /// Reads a configuration file.
+    ///
+    /// Relative directories are resolved against the directory containing
+    /// the file, so a checked-in config works from any subdirectory.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        let mut file: FileConfig = toml::from_str(&text)
+            .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        for dir in [
+            &mut file.content_dir,
+            &mut file.output_dir,
+            &mut file.templates_dir,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if dir.is_relative() {
+                *dir = base.join(&*dir);
+            }
+        }
+
+        Ok(file)
+    }
 }
 
 impl Config {
@@ -40,7 +129,167 @@ impl Config {
             host,
             open_browser,
             broadcast_capacity,
+            in_memory: false,
+            debounce_ms: DEFAULT_DEBOUNCE_MS,
+            filter: FilterOptions::default(),
+            markdown: MarkdownOptions::default(),
+            templates_dir: None,
+            site: toml::Table::new(),
+        }
+    }
+
+    /// Loads the configuration from its layered sources.
+    ///
+    /// Values are applied in increasing order of precedence: built-in
+    /// defaults, the config file, then `LIVE_MD_*` environment variables.
+    /// The config file is `explicit_file` if given, otherwise the first
+    /// `live-md.toml` found in the working directory or its parents.
+    /// Command line flags are applied on top of the result by the caller.
+    pub fn load(explicit_file: Option<&Path>) -> Result<Self> {
+        Self::load_with_env(explicit_file, std::env::vars())
+    }
+
+    /// Loads the configuration like [`Config::load`], reading environment
+    /// overrides from `vars` instead of the process environment
+    pub fn load_with_env<I>(explicit_file: Option<&Path>, vars: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let file = match explicit_file {
+            Some(path) => Some(path.to_path_buf()),
+            None => std::env::current_dir()
+                .ok()
+                .and_then(|dir| Self::find_config_file(&dir)),
+        };
+
+        let mut config = Self::default();
+        if let Some(path) = file {
+            config = config.merge_file(FileConfig::from_file(&path)?);
         }
+        config.merge_env(vars)
+    }
+
+    /// Searches `start` and its ancestors for a `live-md.toml` file
+    pub fn find_config_file(start: &Path) -> Option<PathBuf> {
+        start
+            .ancestors()
+            .map(|dir| dir.join(CONFIG_FILE_NAME))
+            .find(|path| path.is_file())
+    }
+
+    /// Overrides fields with those set in a config file
+    pub fn merge_file(mut self, file: FileConfig) -> Self {
+        if let Some(content_dir) = file.content_dir {
+            self.content_dir = content_dir;
+        }
+        if let Some(output_dir) = file.output_dir {
+            self.output_dir = output_dir;
+        }
+        if let Some(port) = file.port {
+            self.port = port;
+        }
+        if let Some(host) = file.host {
+            self.host = host;
+        }
+        if let Some(open_browser) = file.open_browser {
+            self.open_browser = open_browser;
+        }
+        if let Some(capacity) = file.broadcast_capacity {
+            self.broadcast_capacity = capacity;
+        }
+        if let Some(in_memory) = file.in_memory {
+            self.in_memory = in_memory;
+        }
+        if let Some(debounce_ms) = file.debounce_ms {
+            self.debounce_ms = debounce_ms;
+        }
+        if let Some(filter) = file.filter {
+            self.filter = filter;
+        }
+        if let Some(markdown) = file.markdown {
+            self.markdown = markdown;
+        }
+        if let Some(templates_dir) = file.templates_dir {
+            self.templates_dir = Some(templates_dir);
+        }
+        if let Some(site) = file.site {
+            self.site = site;
+        }
+        self
+    }
+
+    /// Overrides fields with `LIVE_MD_*` variables from `vars`.
+    ///
+    /// Variables without the prefix are ignored, while an unknown name after
+    /// the prefix is an error so that a misspelt setting isn't silently lost.
+    pub fn merge_env<I>(mut self, vars: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        for (key, value) in vars {
+            let Some(name) = key.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let invalid = || format!("Invalid value for {}: {:?}", key, value);
+            match name {
+                "CONTENT_DIR" => self.content_dir = PathBuf::from(&value),
+                "OUTPUT_DIR" => self.output_dir = PathBuf::from(&value),
+                "PORT" => self.port = value.parse().with_context(invalid)?,
+                "HOST" => self.host = value.parse().with_context(invalid)?,
+                "OPEN_BROWSER" => self.open_browser = parse_bool(&value).with_context(invalid)?,
+                "BROADCAST_CAPACITY" => {
+                    self.broadcast_capacity = value.parse().with_context(invalid)?
+                }
+                "IN_MEMORY" => self.in_memory = parse_bool(&value).with_context(invalid)?,
+                "DEBOUNCE_MS" => self.debounce_ms = value.parse().with_context(invalid)?,
+                "TEMPLATES_DIR" => self.templates_dir = Some(PathBuf::from(&value)),
+                "FILTER_INCLUDE" => self.filter.include = parse_list(&value),
+                "FILTER_EXCLUDE" => self.filter.exclude = parse_list(&value),
+                "FILTER_USE_DEFAULT_EXCLUDES" => {
+                    self.filter.use_default_excludes = parse_bool(&value).with_context(invalid)?
+                }
+                "FILTER_USE_IGNORE_FILES" => {
+                    self.filter.use_ignore_files = parse_bool(&value).with_context(invalid)?
+                }
+                "MARKDOWN_DISABLE_TABLES" => {
+                    self.markdown.disable_tables = parse_bool(&value).with_context(invalid)?
+                }
+                "MARKDOWN_DISABLE_FOOTNOTES" => {
+                    self.markdown.disable_footnotes = parse_bool(&value).with_context(invalid)?
+                }
+                "MARKDOWN_DISABLE_STRIKETHROUGH" => {
+                    self.markdown.disable_strikethrough =
+                        parse_bool(&value).with_context(invalid)?
+                }
+                "MARKDOWN_DISABLE_TASKLISTS" => {
+                    self.markdown.disable_tasklists = parse_bool(&value).with_context(invalid)?
+                }
+                "MARKDOWN_DISABLE_SMART_PUNCTUATION" => {
+                    self.markdown.disable_smart_punctuation =
+                        parse_bool(&value).with_context(invalid)?
+                }
+                "MARKDOWN_DISABLE_HIGHLIGHTING" => {
+                    self.markdown.disable_highlighting = parse_bool(&value).with_context(invalid)?
+                }
+                "MARKDOWN_LINE_NUMBERS" => {
+                    self.markdown.line_numbers = parse_bool(&value).with_context(invalid)?
+                }
+                "MARKDOWN_HIGHLIGHT_THEME_LIGHT" => {
+                    self.markdown.highlight_theme_light = Some(value.clone())
+                }
+                "MARKDOWN_HIGHLIGHT_THEME_DARK" => {
+                    self.markdown.highlight_theme_dark = Some(value.clone())
+                }
+                "MARKDOWN_EXTENSIONS" => self.markdown.extensions = parse_list(&value),
+                _ => bail!("Unknown configuration variable: {}", key),
+            }
+        }
+        Ok(self)
+    }
+
+    /// Gets the watcher's debounce window
+    pub fn debounce(&self) -> Duration {
+        Duration::from_millis(self.debounce_ms)
     }
 
     /// Gets the server's socket address
@@ -52,6 +301,38 @@ impl Config {
     pub fn server_url(&self) -> String {
         format!("http://{}:{}", self.host, self.port)
     }
+
+    /// Loads the templates pages are rendered with
+    pub fn templates(&self) -> Result<Templates> {
+        Templates::load(self.templates_dir.as_deref(), &self.site)
+    }
+
+    /// Checks that the configured directories, highlighting themes and
+    /// templates are usable
+    pub fn validate(&self) -> Result<()> {
+        if !self.content_dir.is_dir() {
+            bail!(
+                "Content directory does not exist: {}",
+                self.content_dir.display()
+            );
+        }
+        if self.output_dir.exists() && !self.output_dir.is_dir() {
+            bail!(
+                "Output path exists but is not a directory: {}",
+                self.output_dir.display()
+            );
+        }
+        if self.broadcast_capacity == 0 {
+            bail!("Broadcast capacity must be greater than zero");
+        }
+        ContentFilter::new(&self.content_dir, &self.filter)?;
+        if !self.markdown.disable_highlighting {
+            highlight::validate_theme(self.markdown.light_theme())?;
+            highlight::validate_theme(self.markdown.dark_theme())?;
+        }
+        self.templates()?;
+        Ok(())
+    }
 }
 
 impl Default for Config {
@@ -63,10 +344,35 @@ impl Default for Config {
             host: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
             open_browser: true,
             broadcast_capacity: 16,
+            in_memory: false,
+            debounce_ms: DEFAULT_DEBOUNCE_MS,
+            filter: FilterOptions::default(),
+            markdown: MarkdownOptions::default(),
+            templates_dir: None,
+            site: toml::Table::new(),
         }
     }
 }
 
+/// Parses a comma-separated list, skipping empty entries
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Parses the boolean spellings commonly used in environment variables
+fn parse_bool(value: &str) -> Result<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => bail!("expected a boolean"),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -137,6 +443,171 @@ mod tests {
         assert_eq!(config.content_dir, PathBuf::from("doc"));
         assert_eq!(config.output_dir, PathBuf::from("_dist"));
         assert_eq!(config.broadcast_capacity, 16);
+        assert_eq!(config.debounce_ms, DEFAULT_DEBOUNCE_MS);
         assert!(config.open_browser);
     }
+
+    #[test]
+    fn test_config_validate() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let content_dir = temp_dir.path().join("content");
+        let output_file = temp_dir.path().join("output");
+        std::fs::create_dir_all(&content_dir)?;
+        std::fs::write(&output_file, "not a directory")?;
+
+        let mut config = Config {
+            content_dir: content_dir.clone(),
+            output_dir: temp_dir.path().join("dist"),
+            ..Config::default()
+        };
+        assert!(config.validate().is_ok());
+
+        config.content_dir = temp_dir.path().join("missing");
+        assert!(config.validate().is_err());
+
+        config.content_dir = content_dir;
+        config.output_dir = output_file;
+        assert!(config.validate().is_err());
+
+        config.output_dir = temp_dir.path().join("dist");
+        config.filter.exclude.push("[".to_string());
+        assert!(config.validate().is_err());
+
+        config.filter = FilterOptions::default();
+        config.markdown.highlight_theme_dark = Some("no-such-theme".to_string());
+        assert!(config.validate().is_err());
+
+        // Themes don't matter when highlighting is off
+        config.markdown.disable_highlighting = true;
+        assert!(config.validate().is_ok());
+
+        // Templates must exist and parse
+        let templates_dir = temp_dir.path().join("templates");
+        config.templates_dir = Some(templates_dir.clone());
+        assert!(config.validate().is_err());
+        std::fs::create_dir(&templates_dir)?;
+        std::fs::write(templates_dir.join("page.html"), "{{ content }}")?;
+        assert!(config.validate().is_ok());
+        std::fs::write(templates_dir.join("page.html"), "{% if %}")?;
+        assert!(config.validate().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_config_file() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let nested = temp_dir.path().join("a").join("b");
+        std::fs::create_dir_all(&nested)?;
+
+        assert_eq!(Config::find_config_file(&nested), None);
+
+        let config_path = temp_dir.path().join(CONFIG_FILE_NAME);
+        std::fs::write(&config_path, "port = 4000\n")?;
+        assert_eq!(Config::find_config_file(&nested), Some(config_path));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_config() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let config_path = temp_dir.path().join(CONFIG_FILE_NAME);
+        std::fs::write(
+            &config_path,
+            r#"
+content_dir = "notes"
+output_dir = "/tmp/site"
+port = 4000
+open_browser = false
+templates_dir = "templates"
+
+[filter]
+exclude = ["drafts/"]
+
+[markdown]
+disable_tables = true
+
+[site]
+title = "Handbook"
+"#,
+        )?;
+
+        let config = Config::default().merge_file(FileConfig::from_file(&config_path)?);
+
+        assert_eq!(config.content_dir, temp_dir.path().join("notes"));
+        assert_eq!(config.output_dir, PathBuf::from("/tmp/site"));
+        assert_eq!(config.port, 4000);
+        assert_eq!(config.host, IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
+        assert!(!config.open_browser);
+        // Excludes from the file add to the defaults too
+        assert_eq!(config.filter.exclude, ["drafts/"]);
+        assert!(config.filter.use_default_excludes);
+        assert!(config.filter.use_ignore_files);
+        assert!(config.markdown.disable_tables);
+        assert!(!config.markdown.disable_footnotes);
+        assert_eq!(
+            config.templates_dir,
+            Some(temp_dir.path().join("templates"))
+        );
+        assert_eq!(config.site["title"].as_str(), Some("Handbook"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_config_rejects_unknown_keys() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let config_path = temp_dir.path().join(CONFIG_FILE_NAME);
+        std::fs::write(&config_path, "prot = 4000\n")?;
+
+        assert!(FileConfig::from_file(&config_path).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_env_overrides_file() -> Result<()> {
+        let file = FileConfig {
+            port: Some(4000),
+            content_dir: Some(PathBuf::from("notes")),
+            ..FileConfig::default()
+        };
+        let vars = [
+            ("LIVE_MD_PORT", "5000"),
+            ("LIVE_MD_OPEN_BROWSER", "no"),
+            ("LIVE_MD_MARKDOWN_DISABLE_FOOTNOTES", "1"),
+            ("LIVE_MD_IN_MEMORY", "true"),
+            ("LIVE_MD_DEBOUNCE_MS", "250"),
+            ("LIVE_MD_TEMPLATES_DIR", "layouts"),
+            ("LIVE_MD_FILTER_EXCLUDE", "drafts/, *.tmp"),
+            ("LIVE_MD_FILTER_USE_IGNORE_FILES", "off"),
+            ("LIVE_MD_FILTER_USE_DEFAULT_EXCLUDES", "false"),
+            ("LIVE_MD_MARKDOWN_EXTENSIONS", "md,txt"),
+            ("PORT", "6000"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+
+        let config = Config::default().merge_file(file).merge_env(vars)?;
+
+        assert_eq!(config.port, 5000);
+        assert_eq!(config.content_dir, PathBuf::from("notes"));
+        assert!(!config.open_browser);
+        assert!(config.markdown.disable_footnotes);
+        assert!(config.in_memory);
+        assert_eq!(config.debounce(), Duration::from_millis(250));
+        assert_eq!(config.templates_dir, Some(PathBuf::from("layouts")));
+        assert_eq!(config.filter.exclude, ["drafts/", "*.tmp"]);
+        assert!(!config.filter.use_ignore_files);
+        assert!(!config.filter.use_default_excludes);
+        assert_eq!(config.markdown.extensions, ["md", "txt"]);
+
+        let bad = [("LIVE_MD_PORT".to_string(), "lots".to_string())];
+        assert!(Config::default().merge_env(bad).is_err());
+
+        let unknown = [("LIVE_MD_PROT".to_string(), "5000".to_string())];
+        let err = Config::default().merge_env(unknown).unwrap_err();
+        assert!(err.to_string().contains("LIVE_MD_PROT"));
+
+        Ok(())
+    }
 }
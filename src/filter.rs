@@ -0,0 +1,338 @@
+use anyhow::{Context, Result};
+use ignore::{gitignore::Gitignore, overrides::Override, overrides::OverrideBuilder, WalkBuilder};
+use serde::Deserialize;
+use std::path::{Component, Path, PathBuf};
+
+/// Directories skipped unless `use_default_excludes` is off: dependencies
+/// and build output that are rarely ignored outside of a git repository
+pub const DEFAULT_EXCLUDE: [&str; 2] = ["node_modules/", "target/"];
+
+/// Files read by `use_ignore_files`, in increasing order of precedence
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// Which files in the content directory are part of the site
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterOptions {
+    /// Glob patterns of files to include, relative to the content directory.
+    /// When empty, every file that isn't excluded is included.
+    pub include: Vec<String>,
+
+    /// Glob patterns of files and directories to skip, in `.gitignore` syntax.
+    /// These add to [`DEFAULT_EXCLUDE`] rather than replacing it.
+    pub exclude: Vec<String>,
+
+    /// Skip the [`DEFAULT_EXCLUDE`] directories as well as `exclude`
+    pub use_default_excludes: bool,
+
+    /// Skip files listed in `.gitignore` and `.ignore` files inside the content directory
+    pub use_ignore_files: bool,
+}
+
+impl Default for FilterOptions {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            use_default_excludes: true,
+            use_ignore_files: true,
+        }
+    }
+}
+
+/// Decides which files in the content directory make up the site.
+///
+/// Hidden files and directories are always skipped. Include and exclude
+/// patterns take precedence over ignore files, and an excluded directory
+/// excludes everything inside it. The initial scan and the watcher both go
+/// through this, so a file is either part of the site or not regardless of
+/// how it was found.
+#[derive(Debug, Clone)]
+pub struct ContentFilter {
+    content_dir: PathBuf,
+    overrides: Override,
+    use_ignore_files: bool,
+}
+
+impl ContentFilter {
+    /// Creates a filter for the content directory, checking the patterns
+    pub fn new(content_dir: &Path, options: &FilterOptions) -> Result<Self> {
+        let mut builder = OverrideBuilder::new(content_dir);
+        for pattern in &options.include {
+            builder
+                .add(pattern)
+                .with_context(|| format!("Invalid include pattern: {}", pattern))?;
+        }
+        let defaults = DEFAULT_EXCLUDE
+            .iter()
+            .copied()
+            .filter(|_| options.use_default_excludes);
+        for pattern in defaults.chain(options.exclude.iter().map(String::as_str)) {
+            builder
+                .add(&format!("!{}", pattern))
+                .with_context(|| format!("Invalid exclude pattern: {}", pattern))?;
+        }
+        let overrides = builder.build().context("Invalid content filter")?;
+
+        Ok(Self {
+            content_dir: content_dir.to_path_buf(),
+            overrides,
+            use_ignore_files: options.use_ignore_files,
+        })
+    }
+
+    /// The directory the filter applies to
+    pub fn content_dir(&self) -> &Path {
+        &self.content_dir
+    }
+
+    /// Lists every file in the content directory that is part of the site,
+    /// in path order
+    pub fn files(&self) -> Result<Vec<PathBuf>> {
+        let walk = WalkBuilder::new(&self.content_dir)
+            .hidden(true)
+            .parents(false)
+            .git_global(false)
+            .git_exclude(false)
+            .git_ignore(self.use_ignore_files)
+            .ignore(self.use_ignore_files)
+            .require_git(false)
+            .overrides(self.overrides.clone())
+            .build();
+
+        let mut files = Vec::new();
+        for entry in walk {
+            let entry = entry.with_context(|| {
+                format!(
+                    "Failed to scan content directory: {}",
+                    self.content_dir.display()
+                )
+            })?;
+            if entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_file())
+            {
+                files.push(entry.into_path());
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Checks whether a file is part of the site, the same way `files` does.
+    ///
+    /// The file doesn't need to exist, so removed files can be checked too.
+    pub fn is_included(&self, path: &Path) -> bool {
+        let Ok(rel_path) = path.strip_prefix(&self.content_dir) else {
+            return false;
+        };
+        let names: Vec<_> = rel_path
+            .components()
+            .map(|component| match component {
+                Component::Normal(name) => Some(name),
+                _ => None,
+            })
+            .collect::<Option<_>>()
+            .unwrap_or_default();
+        if names.is_empty() || names.iter().any(|name| is_hidden(name)) {
+            return false;
+        }
+
+        // Check each directory on the way down, since skipping a directory
+        // skips everything inside it
+        let mut current = self.content_dir.clone();
+        for (depth, name) in names.iter().enumerate() {
+            let parent = current.clone();
+            current.push(name);
+            let is_dir = depth + 1 < names.len();
+
+            match self.overrides.matched(&current, is_dir) {
+                ignore::Match::Ignore(_) => return false,
+                ignore::Match::Whitelist(_) => continue,
+                ignore::Match::None => {}
+            }
+            if self.use_ignore_files && self.is_ignored(&parent, &current, is_dir) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Checks the ignore files in `dir` and its ancestors within the content
+    /// directory, where the closest file with a matching pattern decides
+    fn is_ignored(&self, dir: &Path, path: &Path, is_dir: bool) -> bool {
+        for dir in dir.ancestors() {
+            for name in IGNORE_FILES.iter().rev() {
+                let ignore_file = dir.join(name);
+                if !ignore_file.is_file() {
+                    continue;
+                }
+                // Unreadable lines are skipped, as in the scan
+                let (gitignore, _) = Gitignore::new(&ignore_file);
+                match gitignore.matched(path, is_dir) {
+                    ignore::Match::Ignore(_) => return true,
+                    ignore::Match::Whitelist(_) => return false,
+                    ignore::Match::None => {}
+                }
+            }
+            if dir == self.content_dir {
+                break;
+            }
+        }
+        false
+    }
+}
+
+/// Checks whether a file name starts with a dot
+pub(crate) fn is_hidden(name: &std::ffi::OsStr) -> bool {
+    name.to_str().is_some_and(|name| name.starts_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// Creates files with placeholder contents, along with their directories
+    fn create_files(root: &Path, files: &[&str]) -> Result<()> {
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, "content")?;
+        }
+        Ok(())
+    }
+
+    /// Lists the files the filter includes, relative to the content directory
+    fn included(filter: &ContentFilter) -> Result<Vec<String>> {
+        Ok(filter
+            .files()?
+            .iter()
+            .map(|path| {
+                let rel_path = path.strip_prefix(filter.content_dir()).unwrap();
+                rel_path.to_string_lossy().replace('\\', "/")
+            })
+            .collect())
+    }
+
+    #[test]
+    fn test_default_filter() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content_dir = temp_dir.path();
+        create_files(
+            content_dir,
+            &[
+                "index.md",
+                "guide/setup.md",
+                "guide/logo.png",
+                "node_modules/pkg/README.md",
+                "target/doc/notes.md",
+                ".git/HEAD",
+                ".hidden/page.md",
+                "scratch/notes.md",
+                "scratch/keep.md",
+                "guide/draft.md",
+            ],
+        )?;
+        fs::write(
+            content_dir.join(".gitignore"),
+            "scratch/*\n!scratch/keep.md\n",
+        )?;
+        fs::write(content_dir.join("guide").join(".ignore"), "draft.md\n")?;
+
+        let filter = ContentFilter::new(content_dir, &FilterOptions::default())?;
+        assert_eq!(
+            included(&filter)?,
+            [
+                "guide/logo.png",
+                "guide/setup.md",
+                "index.md",
+                "scratch/keep.md"
+            ]
+        );
+
+        // The watcher's checks agree with the scan, including for removed files
+        for file in ["guide/setup.md", "scratch/keep.md", "guide/new.md"] {
+            assert!(filter.is_included(&content_dir.join(file)), "{}", file);
+        }
+        for file in [
+            "node_modules/pkg/README.md",
+            "target/doc/notes.md",
+            ".git/HEAD",
+            ".hidden/page.md",
+            "scratch/notes.md",
+            "guide/draft.md",
+        ] {
+            assert!(!filter.is_included(&content_dir.join(file)), "{}", file);
+        }
+        assert!(!filter.is_included(Path::new("/elsewhere/page.md")));
+
+        // Ignore files can be turned off
+        let options = FilterOptions {
+            use_ignore_files: false,
+            ..FilterOptions::default()
+        };
+        let filter = ContentFilter::new(content_dir, &options)?;
+        assert!(filter.is_included(&content_dir.join("scratch/notes.md")));
+        assert!(included(&filter)?.contains(&"guide/draft.md".to_string()));
+
+        // Excluding more keeps the defaults, unless they are turned off
+        let options = FilterOptions {
+            exclude: vec!["guide/".to_string()],
+            ..FilterOptions::default()
+        };
+        let filter = ContentFilter::new(content_dir, &options)?;
+        assert_eq!(included(&filter)?, ["index.md", "scratch/keep.md"]);
+        let options = FilterOptions {
+            use_default_excludes: false,
+            ..FilterOptions::default()
+        };
+        let filter = ContentFilter::new(content_dir, &options)?;
+        assert!(filter.is_included(&content_dir.join("node_modules/pkg/README.md")));
+        assert!(included(&filter)?.contains(&"target/doc/notes.md".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_include_and_exclude_patterns() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content_dir = temp_dir.path();
+        create_files(
+            content_dir,
+            &[
+                "index.md",
+                "guide/setup.md",
+                "guide/logo.png",
+                "guide/internal/notes.md",
+                "blog/post.md",
+            ],
+        )?;
+        fs::write(content_dir.join(".gitignore"), "blog/post.md\nguide/\n")?;
+
+        let options = FilterOptions {
+            include: vec!["*.md".to_string()],
+            exclude: vec!["internal/".to_string()],
+            use_default_excludes: true,
+            use_ignore_files: true,
+        };
+        let filter = ContentFilter::new(content_dir, &options)?;
+
+        // Included files are kept even when an ignore file lists them, but
+        // not when it lists their directory
+        assert_eq!(included(&filter)?, ["blog/post.md", "index.md"]);
+        assert!(filter.is_included(&content_dir.join("blog/post.md")));
+        assert!(!filter.is_included(&content_dir.join("guide/setup.md")));
+        assert!(!filter.is_included(&content_dir.join("guide/logo.png")));
+        assert!(!filter.is_included(&content_dir.join("guide/internal/notes.md")));
+
+        let invalid = FilterOptions {
+            exclude: vec!["[".to_string()],
+            ..FilterOptions::default()
+        };
+        assert!(ContentFilter::new(content_dir, &invalid).is_err());
+
+        Ok(())
+    }
+}
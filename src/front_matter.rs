@@ -0,0 +1,230 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A front matter block at the top of a markdown file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrontMatter<'a> {
+    /// YAML between `---` lines
+    Yaml(&'a str),
+    /// TOML between `+++` lines
+    Toml(&'a str),
+}
+
+/// Per-page metadata declared in front matter
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PageMeta {
+    /// Overrides the title taken from the first heading
+    pub title: Option<String>,
+
+    /// Short summary, used for the description meta tag
+    pub description: Option<String>,
+
+    /// Publication date, kept as written
+    pub date: Option<String>,
+
+    /// Keywords for the page
+    pub tags: Vec<String>,
+
+    /// Drafts are previewed but left out of the index and static builds
+    pub draft: bool,
+
+    /// Sort key for the index; lower weights come first
+    pub weight: Option<i64>,
+
+    /// Shows a table of contents at the top of the page
+    pub toc: bool,
+
+    /// Name of the template to render the page with
+    pub template: Option<String>,
+
+    /// Any other keys, available to templates as-is
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+impl FrontMatter<'_> {
+    /// Parses the block into page metadata
+    pub fn parse(&self) -> Result<PageMeta> {
+        match self {
+            FrontMatter::Yaml(text) => {
+                // An empty block is valid front matter with no fields
+                if text.trim().is_empty() {
+                    return Ok(PageMeta::default());
+                }
+                serde_yaml::from_str(text).context("Invalid YAML front matter")
+            }
+            FrontMatter::Toml(text) => {
+                let table: toml::Table =
+                    toml::from_str(text).context("Invalid TOML front matter")?;
+                // TOML has a native date type; treat dates as strings like YAML does
+                let mut value = toml::Value::Table(table);
+                stringify_datetimes(&mut value);
+                value.try_into().context("Invalid TOML front matter")
+            }
+        }
+    }
+}
+
+/// Replaces TOML datetimes with their string form
+fn stringify_datetimes(value: &mut toml::Value) {
+    match value {
+        toml::Value::Datetime(datetime) => *value = toml::Value::String(datetime.to_string()),
+        toml::Value::Array(items) => items.iter_mut().for_each(stringify_datetimes),
+        toml::Value::Table(table) => table
+            .iter_mut()
+            .for_each(|(_, value)| stringify_datetimes(value)),
+        _ => {}
+    }
+}
+
+/// Splits a leading front matter block from markdown text.
+///
+/// Returns the block, if there is a complete one, and the markdown that follows it.
+/// A `---` block only counts if it is empty or holds a YAML mapping, so a
+/// document opening with a thematic break is left as ordinary markdown.
+pub fn split_front_matter(text: &str) -> (Option<FrontMatter<'_>>, &str) {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut lines = text.split_inclusive('\n');
+
+    let (closing, is_yaml): (&[&str], bool) = match lines.next().map(str::trim_end) {
+        Some("---") => (&["---", "..."], true),
+        Some("+++") => (&["+++"], false),
+        _ => return (None, text),
+    };
+
+    let start = text.find('\n').map_or(text.len(), |i| i + 1);
+    let mut offset = start;
+    for line in lines {
+        if closing.contains(&line.trim_end()) {
+            let block = &text[start..offset];
+            if is_yaml && !is_yaml_mapping(block) {
+                break;
+            }
+            let body = &text[offset + line.len()..];
+            let front_matter = if is_yaml {
+                FrontMatter::Yaml(block)
+            } else {
+                FrontMatter::Toml(block)
+            };
+            return (Some(front_matter), body);
+        }
+        offset += line.len();
+    }
+
+    // No closing delimiter or no mapping, so this is ordinary markdown
+    (None, text)
+}
+
+/// Checks whether a YAML block is empty or a mapping of keys to values
+fn is_yaml_mapping(block: &str) -> bool {
+    block.trim().is_empty()
+        || matches!(
+            serde_yaml::from_str::<serde_yaml::Value>(block),
+            Ok(serde_yaml::Value::Mapping(_))
+        )
+}
+
+/// Separates front matter from markdown text and parses it.
+///
+/// Text without front matter gets default metadata.
+pub fn parse_front_matter(text: &str) -> Result<(PageMeta, &str)> {
+    match split_front_matter(text) {
+        (Some(front_matter), body) => Ok((front_matter.parse()?, body)),
+        (None, body) => Ok((PageMeta::default(), body)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_yaml_front_matter() {
+        let text = "---\ntitle: Hello\n---\n# Body\n";
+        let (front_matter, body) = split_front_matter(text);
+        assert_eq!(front_matter, Some(FrontMatter::Yaml("title: Hello\n")));
+        assert_eq!(body, "# Body\n");
+    }
+
+    #[test]
+    fn test_split_toml_front_matter() {
+        let text = "+++\r\ntitle = \"Hello\"\r\n+++\r\nBody";
+        let (front_matter, body) = split_front_matter(text);
+        assert_eq!(
+            front_matter,
+            Some(FrontMatter::Toml("title = \"Hello\"\r\n"))
+        );
+        assert_eq!(body, "Body");
+    }
+
+    #[test]
+    fn test_no_front_matter() {
+        for text in [
+            "# Just markdown\n",
+            "---\nunterminated: true\n",
+            "Intro\n---\nnot: front matter\n---\n",
+            "---\n\nA paragraph after a thematic break\n\nSetext heading\n---\n",
+            "---\n- a list\n---\n",
+            "---\n: not yaml [\n---\n",
+            "",
+        ] {
+            assert_eq!(split_front_matter(text), (None, text));
+        }
+    }
+
+    #[test]
+    fn test_parse_yaml_metadata() -> Result<()> {
+        let text = r#"---
+title: Design notes
+description: How the watcher works
+date: 2024-11-16
+tags: [watcher, design]
+draft: true
+weight: 10
+template: wide
+owner: nclack
+reviewers:
+  - alice
+---
+Body
+"#;
+        let (meta, body) = parse_front_matter(text)?;
+
+        assert_eq!(meta.title.as_deref(), Some("Design notes"));
+        assert_eq!(meta.description.as_deref(), Some("How the watcher works"));
+        assert_eq!(meta.date.as_deref(), Some("2024-11-16"));
+        assert_eq!(meta.tags, ["watcher", "design"]);
+        assert!(meta.draft);
+        assert_eq!(meta.weight, Some(10));
+        assert_eq!(meta.template.as_deref(), Some("wide"));
+        assert_eq!(meta.extra["owner"], "nclack");
+        assert_eq!(meta.extra["reviewers"], serde_json::json!(["alice"]));
+        assert_eq!(body, "Body\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_toml_metadata() -> Result<()> {
+        let text = "+++\ntitle = \"Notes\"\ndate = 2024-11-16\nweight = -1\n\n[extra]\nlayout = \"a\"\n+++\n";
+        let (meta, _) = parse_front_matter(text)?;
+
+        assert_eq!(meta.title.as_deref(), Some("Notes"));
+        assert_eq!(meta.date.as_deref(), Some("2024-11-16"));
+        assert_eq!(meta.weight, Some(-1));
+        assert!(!meta.draft);
+        assert_eq!(meta.extra["extra"], serde_json::json!({"layout": "a"}));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_empty_and_invalid_front_matter() {
+        let (meta, body) = parse_front_matter("---\n---\nBody").unwrap();
+        assert_eq!(meta, PageMeta::default());
+        assert_eq!(body, "Body");
+
+        assert!(parse_front_matter("---\nweight: heavy\n---\n").is_err());
+        assert!(parse_front_matter("+++\nnot toml\n+++\n").is_err());
+    }
+}
@@ -0,0 +1,400 @@
+use anyhow::{anyhow, Context, Result};
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    ops::RangeInclusive,
+    sync::{Mutex, OnceLock, PoisonError},
+};
+use syntect::{
+    highlighting::{Theme, ThemeSet},
+    html::{css_for_theme_with_class_style, line_tokens_to_classed_spans, ClassStyle},
+    parsing::{ParseState, ScopeStack, SyntaxSet},
+    util::LinesWithEndings,
+};
+
+/// Theme used when the page is shown in light mode
+pub const DEFAULT_LIGHT_THEME: &str = "InspiredGitHub";
+
+/// Theme used when the page is shown in dark mode
+pub const DEFAULT_DARK_THEME: &str = "base16-ocean.dark";
+
+/// Prefix for the CSS classes emitted for highlighted tokens
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn theme_set() -> &'static ThemeSet {
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+    THEMES.get_or_init(ThemeSet::load_defaults)
+}
+
+/// Names of the built-in highlighting themes
+pub fn theme_names() -> Vec<&'static str> {
+    theme_set().themes.keys().map(String::as_str).collect()
+}
+
+fn find_theme(name: &str) -> Result<&'static Theme> {
+    theme_set().themes.get(name).ok_or_else(|| {
+        anyhow!(
+            "Unknown highlight theme {:?}; available themes: {}",
+            name,
+            theme_names().join(", ")
+        )
+    })
+}
+
+/// Settings parsed from the info string of a fenced code block,
+/// e.g. `rust {3-5,8} linenos`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CodeBlockInfo {
+    /// The language token, if any
+    pub language: Option<String>,
+
+    /// 1-based line ranges to emphasize
+    pub highlighted_lines: Vec<RangeInclusive<usize>>,
+
+    /// Per-block override for showing line numbers
+    pub line_numbers: Option<bool>,
+}
+
+impl CodeBlockInfo {
+    /// Parses a fenced code block info string
+    pub fn parse(info: &str) -> Self {
+        let mut result = Self::default();
+        let info = info.trim();
+
+        // Pull out a `{...}` group of line ranges wherever it appears
+        if let (Some(open), Some(close)) = (info.find('{'), info.rfind('}')) {
+            if open < close {
+                result.highlighted_lines = parse_line_ranges(&info[open + 1..close]);
+                let remainder = format!("{} {}", &info[..open], &info[close + 1..]);
+                return result.with_tokens(&remainder);
+            }
+        }
+
+        result.with_tokens(info)
+    }
+
+    fn with_tokens(mut self, tokens: &str) -> Self {
+        for token in tokens.split(|c: char| c.is_whitespace() || c == ',') {
+            match token {
+                "" => {}
+                "linenos" => self.line_numbers = Some(true),
+                "nolinenos" => self.line_numbers = Some(false),
+                _ if self.language.is_none() => self.language = Some(token.to_string()),
+                _ => {}
+            }
+        }
+        self
+    }
+
+    fn is_highlighted(&self, line_number: usize) -> bool {
+        self.highlighted_lines
+            .iter()
+            .any(|range| range.contains(&line_number))
+    }
+}
+
+/// Parses line ranges like `3-5,8 10` into inclusive ranges, skipping anything malformed
+fn parse_line_ranges(text: &str) -> Vec<RangeInclusive<usize>> {
+    text.split(|c: char| c.is_whitespace() || c == ',')
+        .filter_map(|part| match part.split_once('-') {
+            Some((start, end)) => Some(start.trim().parse().ok()?..=end.trim().parse().ok()?),
+            None => {
+                let line = part.trim().parse().ok()?;
+                Some(line..=line)
+            }
+        })
+        .collect()
+}
+
+/// Renders a fenced code block as highlighted HTML.
+///
+/// The output keeps the `<pre><code class="language-*">` structure of plain
+/// rendering, with each line wrapped in a `span.line` so it can be numbered
+/// or emphasized. Unknown languages are rendered as plain text.
+pub fn highlight_code_block(code: &str, info: &CodeBlockInfo, line_numbers: bool) -> String {
+    let syntaxes = syntax_set();
+    let syntax = info
+        .language
+        .as_deref()
+        .and_then(|language| syntaxes.find_syntax_by_token(language))
+        .unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+
+    let mut parse_state = ParseState::new(syntax);
+    let mut scope_stack = ScopeStack::new();
+
+    let mut class = String::from("highlight");
+    if info.line_numbers.unwrap_or(line_numbers) {
+        class.push_str(" line-numbers");
+    }
+
+    let mut html = format!("<div class=\"{}\"><pre><code", class);
+    if let Some(language) = &info.language {
+        let _ = write!(html, " class=\"language-{}\"", crate::escape_html(language));
+    }
+    html.push('>');
+
+    for (index, line) in LinesWithEndings::from(code).enumerate() {
+        let line = line.trim_end_matches(['\n', '\r']);
+
+        let line_class = if info.is_highlighted(index + 1) {
+            "line highlighted"
+        } else {
+            "line"
+        };
+        let _ = write!(html, "<span class=\"{}\">", line_class);
+
+        // Spans can't cross the line wrapper, so reopen scopes left open by the previous line
+        for scope in scope_stack.as_slice() {
+            let classes: Vec<String> = scope
+                .build_string()
+                .split('.')
+                .map(|atom| format!("hl-{}", atom))
+                .collect();
+            let _ = write!(html, "<span class=\"{}\">", classes.join(" "));
+        }
+
+        let highlighted = parse_state.parse_line(line, syntaxes).ok().and_then(|ops| {
+            line_tokens_to_classed_spans(line, &ops, CLASS_STYLE, &mut scope_stack).ok()
+        });
+        match highlighted {
+            Some((spans, _)) => html.push_str(&spans),
+            None => {
+                // Fall back to plain text for the rest of the block
+                scope_stack = ScopeStack::new();
+                parse_state = ParseState::new(syntaxes.find_syntax_plain_text());
+                html.push_str(&crate::escape_html(line));
+            }
+        }
+
+        for _ in scope_stack.as_slice() {
+            html.push_str("</span>");
+        }
+        html.push_str("</span>\n");
+    }
+
+    html.push_str("</code></pre></div>\n");
+    html
+}
+
+/// Selector for pages switched to dark mode with the theme toggle
+const DARK_SELECTED: &str = ":root[data-theme=\"dark\"]";
+
+/// Selector for pages following the browser's color scheme, i.e. not
+/// switched to light mode with the theme toggle
+const LIGHT_NOT_SELECTED: &str = ":root:not([data-theme=\"light\"])";
+
+/// Generates the stylesheet for highlighted code: the light theme by default
+/// and the dark theme when the page is in dark mode, either by the browser's
+/// preference or by the theme toggle.
+///
+/// Each pair of themes is only generated once.
+pub fn theme_css(light_theme: &str, dark_theme: &str) -> Result<String> {
+    static STYLESHEETS: OnceLock<Mutex<HashMap<(String, String), String>>> = OnceLock::new();
+    let stylesheets = STYLESHEETS.get_or_init(Default::default);
+    let key = (light_theme.to_string(), dark_theme.to_string());
+    if let Some(css) = stylesheets
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&key)
+    {
+        return Ok(css.clone());
+    }
+
+    let css = generate_theme_css(light_theme, dark_theme)?;
+    stylesheets
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(key, css.clone());
+    Ok(css)
+}
+
+fn generate_theme_css(light_theme: &str, dark_theme: &str) -> Result<String> {
+    let light = theme_rules(find_theme(light_theme)?)?;
+    let dark = theme_rules(find_theme(dark_theme)?)?;
+    Ok(format!(
+        "{}\n{}\n@media (prefers-color-scheme: dark) {{\n{}}}\n",
+        light,
+        scope_rules(&dark, DARK_SELECTED),
+        scope_rules(&dark, LIGHT_NOT_SELECTED)
+    ))
+}
+
+/// Prefixes every selector in a flat stylesheet with `scope`, so its rules
+/// only apply inside matching elements and take precedence over unscoped ones
+fn scope_rules(css: &str, scope: &str) -> String {
+    let css = strip_comments(css);
+    let mut scoped = String::new();
+    let mut rest = css.as_str();
+    while let Some(open) = rest.find('{') {
+        let Some(close) = rest[open..].find('}').map(|close| open + close) else {
+            break;
+        };
+        let selectors: Vec<String> = rest[..open]
+            .split(',')
+            .map(|selector| format!("{} {}", scope, selector.trim()))
+            .collect();
+        let _ = writeln!(scoped, "{} {}", selectors.join(", "), &rest[open..=close]);
+        rest = &rest[close + 1..];
+    }
+    scoped
+}
+
+/// Removes `/* ... */` comments from a stylesheet
+fn strip_comments(css: &str) -> String {
+    let mut stripped = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        stripped.push_str(&rest[..start]);
+        rest = rest[start + 2..]
+            .find("*/")
+            .map_or("", |end| &rest[start + 2 + end + 2..]);
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// CSS rules for one theme, including the code block background
+fn theme_rules(theme: &Theme) -> Result<String> {
+    let mut css = css_for_theme_with_class_style(theme, CLASS_STYLE)
+        .context("Failed to generate highlight theme CSS")?;
+
+    let settings = &theme.settings;
+    let mut block = String::new();
+    if let Some(color) = settings.background {
+        let _ = write!(
+            block,
+            "background-color: #{:02x}{:02x}{:02x}; ",
+            color.r, color.g, color.b
+        );
+    }
+    if let Some(color) = settings.foreground {
+        let _ = write!(
+            block,
+            "color: #{:02x}{:02x}{:02x}; ",
+            color.r, color.g, color.b
+        );
+    }
+    if let Some(color) = settings.line_highlight {
+        let _ = writeln!(
+            css,
+            ".highlight .line.highlighted {{ background-color: rgba({}, {}, {}, {:.2}); }}",
+            color.r,
+            color.g,
+            color.b,
+            color.a as f32 / 255.0
+        );
+    }
+    let _ = writeln!(css, ".highlight pre {{ {}}}", block);
+    Ok(css)
+}
+
+/// Checks that a theme name refers to a built-in theme
+pub fn validate_theme(name: &str) -> Result<()> {
+    find_theme(name).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_info_string() {
+        assert_eq!(CodeBlockInfo::parse(""), CodeBlockInfo::default());
+        assert_eq!(
+            CodeBlockInfo::parse("rust"),
+            CodeBlockInfo {
+                language: Some("rust".to_string()),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            CodeBlockInfo::parse("rust {3-5, 8} linenos"),
+            CodeBlockInfo {
+                language: Some("rust".to_string()),
+                highlighted_lines: vec![3..=5, 8..=8],
+                line_numbers: Some(true),
+            }
+        );
+        assert_eq!(
+            CodeBlockInfo::parse("python,nolinenos {x,2}"),
+            CodeBlockInfo {
+                language: Some("python".to_string()),
+                highlighted_lines: vec![2..=2],
+                line_numbers: Some(false),
+            }
+        );
+    }
+
+    #[test]
+    fn test_highlight_code_block() {
+        let info = CodeBlockInfo::parse("rust {2}");
+        let html = highlight_code_block("fn main() {\n    let x = 1;\n}\n", &info, false);
+
+        assert!(html.starts_with("<div class=\"highlight\"><pre><code class=\"language-rust\">"));
+        assert!(html.contains("hl-keyword"));
+        assert_eq!(html.matches("<span class=\"line\">").count(), 2);
+        assert_eq!(html.matches("<span class=\"line highlighted\">").count(), 1);
+        assert_eq!(
+            html.matches("<span").count(),
+            html.matches("</span>").count()
+        );
+    }
+
+    #[test]
+    fn test_spans_balanced_across_lines() {
+        // The block comment scope stays open across lines
+        let info = CodeBlockInfo::parse("rust");
+        let html = highlight_code_block("/* one\ntwo\nthree */ fn x() {}\n", &info, true);
+
+        assert!(html.contains("highlight line-numbers"));
+        for line in html.lines().filter(|l| l.contains("class=\"line")) {
+            let line = line.trim_start_matches(
+                "<div class=\"highlight line-numbers\"><pre><code class=\"language-rust\">",
+            );
+            assert_eq!(
+                line.matches("<span").count(),
+                line.matches("</span>").count(),
+                "unbalanced line: {}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_language_is_escaped_plain_text() {
+        let info = CodeBlockInfo::parse("no-such-language");
+        let html = highlight_code_block("a < b && c\n", &info, false);
+
+        assert!(html.contains("class=\"language-no-such-language\""));
+        assert!(html.contains("a &lt; b &amp;&amp; c"));
+    }
+
+    #[test]
+    fn test_scope_rules() {
+        let css = "/* theme {x} */\n.a {\n color: red;\n}\n.b .c, .d { color: blue; }\n";
+        assert_eq!(
+            scope_rules(css, ".dark"),
+            ".dark .a {\n color: red;\n}\n.dark .b .c, .dark .d { color: blue; }\n"
+        );
+    }
+
+    #[test]
+    fn test_theme_css() {
+        let css = theme_css(DEFAULT_LIGHT_THEME, DEFAULT_DARK_THEME).unwrap();
+        assert!(css.contains(".hl-"));
+        assert!(css.contains("@media (prefers-color-scheme: dark)"));
+        assert!(css.contains(".highlight pre"));
+
+        // The dark theme follows the toggle as well as the browser
+        assert!(css.contains(":root[data-theme=\"dark\"] .highlight pre {"));
+        assert!(css.contains(":root:not([data-theme=\"light\"]) .highlight pre {"));
+
+        assert!(theme_css("no-such-theme", DEFAULT_DARK_THEME).is_err());
+        assert!(validate_theme(DEFAULT_LIGHT_THEME).is_ok());
+    }
+}
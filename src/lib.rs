@@ -1,10 +1,33 @@
+pub mod assets;
+pub mod cache;
+pub mod cli;
 pub mod config;
+pub mod filter;
+pub mod front_matter;
+pub mod highlight;
+pub mod live;
 pub mod markdown;
+pub mod memory;
+pub mod nav;
 pub mod server;
+pub mod site;
+pub mod templates;
+pub mod toc;
 pub mod watcher;
 
-use anyhow::Result;
-use std::path::PathBuf;
+use anyhow::{anyhow, bail, Context, Result};
+use cache::{RenderCache, Reuse};
+use rayon::prelude::*;
+use site::{Page, Site};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use templates::{IndexEntry, Templates};
+
+/// File name of the generated page listing
+pub const INDEX_LISTING_FILE: &str = "_index.html";
 
 /// Formats a filename into a readable display name:
 /// - Adds spaces before numbers
@@ -36,113 +59,361 @@ fn format_display_name(name: &str) -> String {
     }
 }
 
-/// Renders all markdown files in the content directory to HTML files in the output directory
+/// Renders all markdown files in the content directory to HTML files in the output directory.
+///
+/// Pages are rendered in parallel. When serving with `live_reload`, output
+/// left by a previous run is reused when the page's source, the site
+/// navigation and the page template are unchanged, as recorded in the output
+/// directory's render cache. Pages whose source is unchanged but whose
+/// navigation isn't are laid out again without rendering their markdown.
+///
+/// Every file is attempted even if some fail; the returned error lists each failure,
+/// including files left out because another renders to the same page.
+/// When `live_reload` is false the pages are standalone and can be published as-is,
+/// so drafts are left out and no render cache is kept alongside them.
 pub fn render_all_markdown_files(
     content_dir: &std::path::Path,
     output_dir: &std::path::Path,
-) -> Result<Vec<PathBuf>> {
-    let mut markdown_files = Vec::new();
-    collect_markdown_files(content_dir, content_dir, &mut markdown_files)?;
-
-    // Render each markdown file
-    for path in &markdown_files {
-        markdown::render_markdown_file(path, output_dir)?;
+    options: &markdown::MarkdownOptions,
+    filter: &filter::FilterOptions,
+    templates: &Templates,
+    live_reload: bool,
+) -> Result<RenderStats> {
+    let start = Instant::now();
+    let site = Site::scan(&filter::ContentFilter::new(content_dir, filter)?, options)?;
+    let markdown_files: Vec<PathBuf> = site
+        .pages()
+        .filter(|page| live_reload || !page.meta.draft)
+        .map(|page| page.source.clone())
+        .collect();
+    let scan_time = start.elapsed();
+
+    let start = Instant::now();
+    let mut cache = load_render_cache(output_dir, options, templates, live_reload)?;
+    let results = render_pages(
+        &site,
+        &markdown_files,
+        content_dir,
+        output_dir,
+        templates,
+        live_reload,
+        &mut cache,
+    );
+
+    let mut stats = RenderStats::default();
+    let mut failures: Vec<String> = site.conflicts().map(|(_, e)| format!("{:#}", e)).collect();
+    let attempted = markdown_files.len() + failures.len();
+    for result in results {
+        match result {
+            Ok(PageRender::Unchanged(_)) => stats.unchanged += 1,
+            Ok(PageRender::Refreshed(_)) => stats.refreshed += 1,
+            Ok(PageRender::Rendered(_)) => stats.rendered += 1,
+            Err(e) => failures.push(format!("{:#}", e)),
+        }
     }
+    // Forget removed pages
+    let keys: HashSet<&Path> = markdown_files
+        .iter()
+        .map(|path| path.strip_prefix(content_dir).unwrap_or(path))
+        .collect();
+    cache.retain(|key| keys.contains(key));
 
     // Generate index.html
-    generate_index_html(output_dir, &markdown_files, content_dir)?;
+    generate_index_html(
+        output_dir,
+        site.pages(),
+        content_dir,
+        options,
+        templates,
+        live_reload,
+    )?;
+    if live_reload {
+        cache.save(output_dir)?;
+    }
+    stats.scan_time = scan_time;
+    stats.render_time = start.elapsed();
+
+    if !failures.is_empty() {
+        bail!(
+            "Failed to render {} of {} markdown files:\n  {}",
+            failures.len(),
+            attempted,
+            failures.join("\n  ")
+        );
+    }
 
-    Ok(markdown_files)
+    stats.files = markdown_files;
+    Ok(stats)
 }
 
-/// Recursively collect markdown files from a directory
-pub fn collect_markdown_files(
-    current_dir: &std::path::Path,
-    _base_dir: &std::path::Path,
-    files: &mut Vec<PathBuf>,
-) -> Result<()> {
-    let entries = std::fs::read_dir(current_dir)?;
+/// Summary of a full-site render
+#[derive(Debug, Clone, Default)]
+pub struct RenderStats {
+    /// Every page in the output, whether rendered or reused
+    pub files: Vec<PathBuf>,
 
-    for entry in entries {
-        let entry = entry?;
-        let path = entry.path();
+    /// Pages rendered in this run
+    pub rendered: usize,
 
-        if path.is_file() && path.extension().map_or(false, |ext| ext == "md") {
-            files.push(path);
-        } else if path.is_dir() {
-            collect_markdown_files(&path, _base_dir, files)?;
-        }
+    /// Pages laid out again with new navigation, reusing their rendered markdown
+    pub refreshed: usize,
+
+    /// Pages whose output from a previous run was still up to date
+    pub unchanged: usize,
+
+    /// Time spent scanning the content directory
+    pub scan_time: Duration,
+
+    /// Time spent rendering pages and the index
+    pub render_time: Duration,
+}
+
+impl std::fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} pages: {} rendered, {} refreshed, {} unchanged in {:.2?} (scan {:.2?}, render {:.2?})",
+            self.files.len(),
+            self.rendered,
+            self.refreshed,
+            self.unchanged,
+            self.scan_time + self.render_time,
+            self.scan_time,
+            self.render_time
+        )
     }
+}
 
-    Ok(())
+/// Outcome of rendering a page, with the path of its HTML file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PageRender {
+    /// The output from a previous run is up to date
+    Unchanged(PathBuf),
+    /// The page was laid out again from its cached content, such as for new navigation
+    Refreshed(PathBuf),
+    /// The page was rendered
+    Rendered(PathBuf),
 }
 
-/// Generate index.html with links to all rendered files
-pub fn generate_index_html(
-    output_dir: &std::path::Path,
-    markdown_files: &[PathBuf],
-    content_dir: &std::path::Path,
-) -> Result<()> {
-    let mut html_content = String::from(include_str!("templates/index-start.html"));
-
-    // Sort files for consistent ordering
-    let mut sorted_files = markdown_files.to_vec();
-    sorted_files.sort();
-
-    // Add links to each file
-    for path in sorted_files {
-        if let Ok(rel_path) = path.strip_prefix(content_dir) {
-            let file_stem = rel_path
-                .file_stem()
-                .and_then(|s| s.to_str())
-                .unwrap_or_default();
-            let parent = rel_path.parent().and_then(|p| p.to_str()).unwrap_or("");
+impl PageRender {
+    /// Path of the page's HTML file
+    pub fn output(&self) -> &Path {
+        match self {
+            PageRender::Unchanged(output)
+            | PageRender::Refreshed(output)
+            | PageRender::Rendered(output) => output,
+        }
+    }
+}
 
-            // Create the HTML path, preserving directory structure
-            let html_path = if !parent.is_empty() {
-                format!("{}/{}.html", parent, file_stem)
-            } else {
-                format!("{}.html", file_stem)
+/// Renders pages of the site in parallel with the site's options, skipping
+/// those whose output the cache shows is up to date.
+///
+/// The navigation and code highlighting styles are built once for the whole
+/// site. Pages whose markdown is
+/// unchanged but whose navigation isn't are laid out again from the content
+/// in the cache. The cache records the pages rendered and forgets those that
+/// failed, so they are retried. Returns the outcome for each source, in order.
+pub fn render_pages(
+    site: &Site,
+    sources: &[PathBuf],
+    content_dir: &Path,
+    output_dir: &Path,
+    templates: &Templates,
+    live_reload: bool,
+    cache: &mut RenderCache,
+) -> Vec<Result<PageRender>> {
+    let options = site.options();
+    let nav = nav::Nav::for_site(site, content_dir);
+    let styles = options.highlight_css();
+
+    // Renders a page unless the cache shows its output is up to date,
+    // returning what to record in the cache if it was written
+    let render_changed_page =
+        |source: &PathBuf| -> Result<(PageRender, Option<(String, markdown::PageContent)>)> {
+            let markdown = std::fs::read(source)
+                .with_context(|| format!("Failed to read markdown file: {}", source.display()))?;
+            // Templates may show when the page was last modified
+            let modified = markdown::last_modified(source)
+                .map(templates::format_timestamp)
+                .unwrap_or_default();
+            let inputs = cache::fingerprint([markdown.as_slice(), modified.as_bytes()]);
+
+            let key = source.strip_prefix(content_dir).unwrap_or(source);
+            let output = markdown::get_output_path(source, content_dir, output_dir, options)?;
+            let (content, refreshed) = match cache.reuse(key, &inputs, nav.fingerprint(), &output) {
+                Reuse::Output => return Ok((PageRender::Unchanged(output), None)),
+                Reuse::Content(content) => (content.clone(), true),
+                Reuse::Nothing => (markdown::render_page_content(source, options)?, false),
             };
 
-            // Create a display name with proper formatting
-            let display_name = format_display_name(file_stem);
-            let display_path = if !parent.is_empty() {
-                format!("<span class=\"path\">in {}</span>", parent)
+            let styles = styles.as_deref().map_err(|e| anyhow!("{:#}", e))?;
+            let html = markdown::layout_page(
+                source,
+                &content,
+                templates,
+                &nav.render(source),
+                styles,
+                live_reload,
+            )?;
+            let output =
+                markdown::write_page_html(source, content_dir, output_dir, options, &html)?;
+            let render = if refreshed {
+                PageRender::Refreshed(output)
             } else {
-                String::new()
+                PageRender::Rendered(output)
             };
+            Ok((render, Some((inputs, content))))
+        };
+    let results: Vec<_> = sources.par_iter().map(render_changed_page).collect();
+
+    sources
+        .iter()
+        .zip(results)
+        .map(|(source, result)| {
+            let key = source.strip_prefix(content_dir).unwrap_or(source);
+            match result {
+                Ok((render, written)) => {
+                    if let Some((inputs, content)) = written {
+                        cache.insert(key, inputs, nav.fingerprint(), content, render.output());
+                    }
+                    Ok(render)
+                }
+                Err(e) => {
+                    cache.forget(key);
+                    Err(e)
+                }
+            }
+        })
+        .collect()
+}
 
-            html_content.push_str(&format!(
-                "        <li><a href=\"{}\">{}{}</a></li>\n",
-                html_path, display_name, display_path
-            ));
-        }
+/// Loads the render cache kept in the output directory for pages rendered
+/// with these options and templates.
+///
+/// Without `live_reload` the output is meant to be published, so a cache
+/// left there by the server is removed and an empty one is used instead.
+pub fn load_render_cache(
+    output_dir: &Path,
+    options: &markdown::MarkdownOptions,
+    templates: &Templates,
+    live_reload: bool,
+) -> Result<RenderCache> {
+    if !live_reload {
+        RenderCache::remove(output_dir)?;
+        return Ok(RenderCache::default());
     }
+    let template = template_fingerprint(options, templates, live_reload)?;
+    Ok(RenderCache::load(output_dir, &template))
+}
+
+/// Fingerprint of everything besides a page's source and navigation that
+/// goes into rendering it: the templates, styles and options
+fn template_fingerprint(
+    options: &markdown::MarkdownOptions,
+    templates: &Templates,
+    live_reload: bool,
+) -> Result<String> {
+    let styles = options.highlight_css()?;
+    Ok(cache::fingerprint([
+        templates.fingerprint().as_bytes(),
+        styles.as_bytes(),
+        options.fingerprint().as_bytes(),
+        &[u8::from(live_reload)],
+    ]))
+}
 
-    html_content.push_str(include_str!("templates/index-end.html"));
+/// Collects the markdown files the filter includes, in path order
+pub fn collect_markdown_files(
+    filter: &filter::ContentFilter,
+    options: &markdown::MarkdownOptions,
+) -> Result<Vec<PathBuf>> {
+    let mut files = filter.files()?;
+    files.retain(|path| options.is_markdown(path));
+    Ok(files)
+}
 
-    // Write index.html to output directory
-    let index_path = output_dir.join("index.html");
-    std::fs::write(index_path, html_content)?;
+/// Generate a listing with links to all rendered pages.
+///
+/// Pages are listed as a tree following the content directory, ordered by
+/// front matter weight, then file name. Drafts are not listed.
+///
+/// The listing is always written to `_index.html`. It is also written to
+/// `index.html` unless the content directory has a README.md or index.md
+/// landing page, which is rendered there instead.
+pub fn generate_index_html<'a>(
+    output_dir: &std::path::Path,
+    pages: impl IntoIterator<Item = &'a Page>,
+    content_dir: &std::path::Path,
+    options: &markdown::MarkdownOptions,
+    templates: &Templates,
+    live_reload: bool,
+) -> Result<()> {
+    let pages: Vec<&Page> = pages.into_iter().collect();
+    let html_content = index_listing_html(
+        pages.iter().copied(),
+        content_dir,
+        options,
+        templates,
+        live_reload,
+    )?;
+
+    // Write the listing to the output directory
+    std::fs::create_dir_all(output_dir)?;
+    std::fs::write(output_dir.join(INDEX_LISTING_FILE), &html_content)?;
+
+    // Published landing pages take over index.html
+    let has_landing_page = pages.iter().any(|page| {
+        page.source.parent() == Some(content_dir)
+            && nav::is_directory_index(&page.source, options)
+            && (live_reload || !page.meta.draft)
+    });
+    if !has_landing_page {
+        std::fs::write(output_dir.join("index.html"), html_content)?;
+    }
 
     Ok(())
 }
 
-/// Sets up an HTML template with live reload capability
-pub fn wrap_html_template(content: &str, title: &str) -> String {
-    format!(
-        "{}{}{}",
-        include_str!("templates/page-start.html"),
-        content,
-        include_str!("templates/page-end.html")
-    )
-    .replace("{{title}}", title)
+/// Renders the page listing as a complete HTML page
+pub fn index_listing_html<'a>(
+    pages: impl IntoIterator<Item = &'a Page>,
+    content_dir: &std::path::Path,
+    options: &markdown::MarkdownOptions,
+    templates: &Templates,
+    live_reload: bool,
+) -> Result<String> {
+    let pages: Vec<&Page> = pages.into_iter().collect();
+    let listing = nav::render_index_list(pages.iter().copied(), content_dir, options);
+
+    // Templates get the listed pages too, to lay out as they like
+    let entries: Vec<IndexEntry> = pages
+        .iter()
+        .filter(|page| !page.meta.draft)
+        .filter_map(|page| {
+            Some(IndexEntry {
+                title: &page.title,
+                url: nav::page_href(&page.source, content_dir, options)?,
+                meta: &page.meta,
+            })
+        })
+        .collect();
+    templates.render_index(&listing, &entries, live_reload)
+}
+
+/// Escapes text for inclusion in HTML
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    // Writing to a String can't fail
+    let _ = pulldown_cmark::escape::escape_html(&mut escaped, text);
+    escaped
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use filter::{ContentFilter, FilterOptions};
+    use markdown::MarkdownOptions;
     use std::fs;
     use tempfile::TempDir;
 
@@ -165,10 +436,24 @@ mod tests {
         fs::write(base_path.join("test2.md"), "# Test 2")?;
         fs::create_dir(base_path.join("subdir"))?;
         fs::write(base_path.join("subdir").join("test3.md"), "# Test 3")?;
-
-        let mut files = Vec::new();
-        collect_markdown_files(base_path, base_path, &mut files)?;
-
+        fs::write(base_path.join("subdir").join("test4.markdown"), "# Test 4")?;
+        fs::write(base_path.join("subdir").join("notes.txt"), "notes")?;
+        fs::create_dir_all(base_path.join("node_modules").join("pkg"))?;
+        fs::write(
+            base_path.join("node_modules").join("pkg").join("README.md"),
+            "",
+        )?;
+
+        let filter = ContentFilter::new(base_path, &FilterOptions::default())?;
+        let files = collect_markdown_files(&filter, &MarkdownOptions::default())?;
+        assert_eq!(files.len(), 4);
+
+        // Only the configured extensions count as pages
+        let options = MarkdownOptions {
+            extensions: vec!["md".to_string()],
+            ..MarkdownOptions::default()
+        };
+        let files = collect_markdown_files(&filter, &options)?;
         assert_eq!(files.len(), 3);
         assert!(files.iter().all(|p| p.extension().unwrap() == "md"));
         Ok(())
@@ -181,26 +466,322 @@ mod tests {
         let output_dir = temp_dir.path();
 
         // Create test markdown files
-        let files = vec![content_dir.join("test1.md"), content_dir.join("test2.md")];
-
-        generate_index_html(output_dir, &files, content_dir)?;
+        let files = [content_dir.join("test1.md"), content_dir.join("test2.md")];
+        let pages: Vec<Page> = files.iter().map(|path| Page::load(path)).collect();
+
+        generate_index_html(
+            output_dir,
+            &pages,
+            content_dir,
+            &MarkdownOptions::default(),
+            &Templates::default(),
+            true,
+        )?;
 
         let index_content = fs::read_to_string(output_dir.join("index.html"))?;
         assert!(index_content.contains("Test 1"));
         assert!(index_content.contains("Test 2"));
+        assert!(!index_content.contains("{{theme}}"));
+        assert!(index_content.contains("class=\"theme-toggle\""));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_all_without_live_reload() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content_dir = temp_dir.path().join("content");
+        let output_dir = temp_dir.path().join("output");
+        fs::create_dir_all(&content_dir)?;
+        fs::write(content_dir.join("page.md"), "# Page")?;
+
+        let options = markdown::MarkdownOptions::default();
+        render_all_markdown_files(
+            &content_dir,
+            &output_dir,
+            &options,
+            &FilterOptions::default(),
+            &Templates::default(),
+            false,
+        )?;
+
+        for file in ["page.html", "index.html"] {
+            let html = fs::read_to_string(output_dir.join(file))?;
+            assert!(!html.contains("EventSource"), "{} has live reload", file);
+        }
+
+        // Built output holds only the site, even where the server kept a cache
+        assert!(!output_dir.join(cache::CACHE_FILE).exists());
+        fs::write(output_dir.join(cache::CACHE_FILE), "{}")?;
+        render_all_markdown_files(
+            &content_dir,
+            &output_dir,
+            &options,
+            &FilterOptions::default(),
+            &Templates::default(),
+            false,
+        )?;
+        assert!(!output_dir.join(cache::CACHE_FILE).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_orders_by_weight_and_hides_drafts() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content_dir = temp_dir.path().join("content");
+        let output_dir = temp_dir.path().join("output");
+        fs::create_dir_all(&content_dir)?;
+        fs::write(content_dir.join("a.md"), "# Unweighted")?;
+        fs::write(content_dir.join("b.md"), "---\nweight: 2\n---\n# Second")?;
+        fs::write(content_dir.join("c.md"), "---\nweight: 1\n---\n# First")?;
+        fs::write(content_dir.join("d.md"), "---\ndraft: true\n---\n# Draft")?;
+
+        let options = markdown::MarkdownOptions::default();
+        render_all_markdown_files(
+            &content_dir,
+            &output_dir,
+            &options,
+            &FilterOptions::default(),
+            &Templates::default(),
+            true,
+        )?;
+
+        let index = fs::read_to_string(output_dir.join("index.html"))?;
+        let first = index.find("First").unwrap();
+        let second = index.find("Second").unwrap();
+        let unweighted = index.find("Unweighted").unwrap();
+        assert!(first < second && second < unweighted);
+        assert!(!index.contains("Draft"));
+
+        // Drafts are previewed, but not published
+        assert!(output_dir.join("d.html").exists());
+        fs::remove_dir_all(&output_dir)?;
+        render_all_markdown_files(
+            &content_dir,
+            &output_dir,
+            &options,
+            &FilterOptions::default(),
+            &Templates::default(),
+            false,
+        )?;
+        assert!(!output_dir.join("d.html").exists());
+        assert!(output_dir.join("a.html").exists());
 
         Ok(())
     }
 
     #[test]
-    fn test_wrap_html_template() {
-        let content = "<p>Test content</p>";
-        let title = "Test Title";
-        let result = wrap_html_template(content, title);
-
-        assert!(result.contains(content));
-        assert!(result.contains(title));
-        assert!(result.contains("<!DOCTYPE html>"));
-        assert!(result.contains("</html>"));
+    fn test_pages_have_navigation() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content_dir = temp_dir.path().join("content");
+        let output_dir = temp_dir.path().join("output");
+        fs::create_dir_all(content_dir.join("guide"))?;
+        fs::write(content_dir.join("intro.md"), "# Intro")?;
+        fs::write(content_dir.join("guide").join("setup.md"), "# Setup")?;
+
+        let options = markdown::MarkdownOptions::default();
+        render_all_markdown_files(
+            &content_dir,
+            &output_dir,
+            &options,
+            &FilterOptions::default(),
+            &Templates::default(),
+            false,
+        )?;
+
+        let intro = fs::read_to_string(output_dir.join("intro.html"))?;
+        assert!(intro.contains("<nav class=\"site-nav\">"));
+        assert!(intro.contains("<a href=\"intro.html\" class=\"current\""));
+        assert!(intro.contains("<a href=\"guide/setup.html\">Setup</a>"));
+
+        let setup = fs::read_to_string(output_dir.join("guide").join("setup.html"))?;
+        assert!(setup.contains("<a href=\"../intro.html\">Intro</a>"));
+
+        let index = fs::read_to_string(output_dir.join("index.html"))?;
+        assert!(index.contains("<summary>Guide</summary>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_readme_is_landing_page() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content_dir = temp_dir.path().join("content");
+        let output_dir = temp_dir.path().join("output");
+        fs::create_dir_all(content_dir.join("guide"))?;
+        fs::create_dir_all(content_dir.join("api"))?;
+        fs::write(content_dir.join("page.md"), "# Page")?;
+        fs::write(content_dir.join("README.md"), "# Welcome")?;
+        fs::write(content_dir.join("guide").join("README.md"), "# Guide home")?;
+        fs::write(content_dir.join("api").join("index.md"), "# API home")?;
+        fs::write(content_dir.join("api").join("README.md"), "# API readme")?;
+
+        let options = markdown::MarkdownOptions::default();
+        render_all_markdown_files(
+            &content_dir,
+            &output_dir,
+            &options,
+            &FilterOptions::default(),
+            &Templates::default(),
+            false,
+        )?;
+
+        let index = fs::read_to_string(output_dir.join("index.html"))?;
+        assert!(index.contains("<h1 id=\"welcome\">Welcome"));
+        let listing = fs::read_to_string(output_dir.join(INDEX_LISTING_FILE))?;
+        assert!(listing.contains("<a href=\"page.html\">Page</a>"));
+
+        // Nested landing pages, with index.md preferred over README.md
+        let guide = fs::read_to_string(output_dir.join("guide").join("index.html"))?;
+        assert!(guide.contains("Guide home"));
+        let api = fs::read_to_string(output_dir.join("api").join("index.html"))?;
+        assert!(api.contains("API home"));
+        assert!(output_dir.join("api").join("README.html").exists());
+
+        // Without a landing page the listing is the index
+        fs::remove_file(content_dir.join("README.md"))?;
+        fs::remove_dir_all(&output_dir)?;
+        render_all_markdown_files(
+            &content_dir,
+            &output_dir,
+            &options,
+            &FilterOptions::default(),
+            &Templates::default(),
+            false,
+        )?;
+        let index = fs::read_to_string(output_dir.join("index.html"))?;
+        assert!(index.contains("<a href=\"page.html\">Page</a>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_all_reuses_unchanged_pages() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content_dir = temp_dir.path().join("content");
+        let output_dir = temp_dir.path().join("output");
+        fs::create_dir_all(&content_dir)?;
+        fs::write(content_dir.join("a.md"), "# A")?;
+        fs::write(content_dir.join("b.md"), "# B")?;
+        fs::write(content_dir.join("c.md"), "# C")?;
+
+        let options = markdown::MarkdownOptions::default();
+        let filter = FilterOptions::default();
+        let templates = Templates::default();
+        let render = |options: &markdown::MarkdownOptions, templates: &Templates| {
+            let stats = render_all_markdown_files(
+                &content_dir,
+                &output_dir,
+                options,
+                &filter,
+                templates,
+                true,
+            )?;
+            assert_eq!(
+                stats.files.len(),
+                stats.rendered + stats.refreshed + stats.unchanged
+            );
+            Ok::<_, anyhow::Error>((stats.rendered, stats.refreshed, stats.unchanged))
+        };
+        assert_eq!(render(&options, &templates)?, (3, 0, 0));
+        assert_eq!(render(&options, &templates)?, (0, 0, 3));
+
+        // Editing a body re-renders just that page
+        fs::write(content_dir.join("a.md"), "# A\n\nMore")?;
+        assert_eq!(render(&options, &templates)?, (1, 0, 2));
+        assert!(fs::read_to_string(output_dir.join("a.html"))?.contains("More"));
+
+        // Losing its output lays the page out again from the cached markdown
+        fs::remove_file(output_dir.join("b.html"))?;
+        assert_eq!(render(&options, &templates)?, (0, 1, 2));
+        assert!(output_dir.join("b.html").exists());
+
+        // A new page changes every page's navigation, which is laid out
+        // again around the cached markdown
+        fs::write(content_dir.join("d.md"), "# D")?;
+        assert_eq!(render(&options, &templates)?, (1, 3, 0));
+        assert!(fs::read_to_string(output_dir.join("a.html"))?.contains("d.html"));
+        assert_eq!(render(&options, &templates)?, (0, 0, 4));
+
+        // Editing a body leaves the navigation alone
+        fs::write(content_dir.join("c.md"), "# C\n\nMore")?;
+        assert_eq!(render(&options, &templates)?, (1, 0, 3));
+
+        // As do different options
+        let options = markdown::MarkdownOptions {
+            line_numbers: true,
+            ..options
+        };
+        assert_eq!(render(&options, &templates)?, (4, 0, 0));
+        assert_eq!(render(&options, &templates)?, (0, 0, 4));
+
+        // And different templates
+        let templates_dir = temp_dir.path().join("templates");
+        fs::create_dir_all(&templates_dir)?;
+        fs::write(
+            templates_dir.join("page.html"),
+            "<main>{{ content }}</main>",
+        )?;
+        let templates = Templates::load(Some(&templates_dir), &toml::Table::new())?;
+        assert_eq!(render(&options, &templates)?, (4, 0, 0));
+        assert_eq!(render(&options, &templates)?, (0, 0, 4));
+        assert!(fs::read_to_string(output_dir.join("a.html"))?.starts_with("<main>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_all_reports_every_failure() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content_dir = temp_dir.path().join("content");
+        let output_dir = temp_dir.path().join("output");
+        fs::create_dir_all(&content_dir)?;
+        fs::write(content_dir.join("good.md"), "# Good")?;
+        fs::write(content_dir.join("bad1.md"), [0xff, 0xfe])?;
+        fs::write(content_dir.join("bad2.md"), [0xff, 0xfe])?;
+
+        let options = markdown::MarkdownOptions::default();
+        let err = render_all_markdown_files(
+            &content_dir,
+            &output_dir,
+            &options,
+            &FilterOptions::default(),
+            &Templates::default(),
+            false,
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("2 of 3"));
+        assert!(err.contains("bad1.md"));
+        assert!(err.contains("bad2.md"));
+        assert!(output_dir.join("good.html").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_all_reports_conflicting_pages() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content_dir = temp_dir.path().join("content");
+        let output_dir = temp_dir.path().join("output");
+        fs::create_dir_all(&content_dir)?;
+        fs::write(content_dir.join("notes.md"), "# From md")?;
+        fs::write(content_dir.join("notes.markdown"), "# From markdown")?;
+
+        let err = render_all_markdown_files(
+            &content_dir,
+            &output_dir,
+            &MarkdownOptions::default(),
+            &FilterOptions::default(),
+            &Templates::default(),
+            false,
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("1 of 2"));
+        assert!(err.contains("notes.markdown renders to the same page as"));
+        let html = fs::read_to_string(output_dir.join("notes.html"))?;
+        assert!(html.contains("From md"));
+        let index = fs::read_to_string(output_dir.join("index.html"))?;
+        assert_eq!(index.matches("href=\"notes.html\"").count(), 1);
+        Ok(())
     }
 }
@@ -0,0 +1,461 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// An update broadcast to connected browsers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LiveEvent {
+    /// Changed files were picked up and the site is being updated
+    Building,
+
+    /// A batch of files changed; sent once the site is up to date
+    Changed(Vec<Change>),
+
+    /// A file couldn't be rendered or copied; sent instead of its change
+    Error(BuildError),
+
+    /// The site is up to date after rendering `rendered` pages
+    Built { rendered: usize, duration: Duration },
+}
+
+/// A change in the content directory, broadcast to connected browsers once
+/// the site is up to date
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// The file that changed
+    pub path: PathBuf,
+
+    /// Path of the page built from the file relative to the site root, like
+    /// `guide/setup.html`, when the file is markdown
+    pub url: Option<String>,
+
+    /// Inner HTML of the page's `<body>`, when the page was re-rendered
+    pub body: Option<String>,
+
+    /// Whether pages were added, removed or retitled, which changes the
+    /// navigation on every page as well as the page listing
+    pub pages_changed: bool,
+}
+
+/// A failure while updating the site after a change, shown in the browser
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BuildError {
+    /// The file that failed, relative to the content directory
+    pub file: String,
+
+    /// The page built from the file, or `None` if it isn't a page
+    pub url: Option<String>,
+
+    /// What went wrong
+    pub message: String,
+
+    /// The underlying causes, outermost first
+    pub context: Vec<String>,
+}
+
+/// Message sent to the live reload script
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum LiveMessage {
+    /// Reload the page; `url` is the page that changed, if any. Over a
+    /// WebSocket `scroll` is the position the browser last reported, to
+    /// return to after reloading.
+    Reload {
+        url: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        scroll: Option<u64>,
+    },
+
+    /// Replace the body of the page at `url`; other pages reload
+    Patch { url: String, body: String },
+
+    /// Progress of the site update after a change
+    BuildStatus(BuildStatus),
+
+    /// A file failed to build; the page keeps its old content
+    Error(BuildError),
+}
+
+/// Whether the site is being updated, sent as the `state` of a build status message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum BuildStatus {
+    Building,
+    Done { rendered: usize, duration_ms: u64 },
+}
+
+/// The browser an event is being sent to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Viewer<'a> {
+    /// An event stream opened without a page, which hears about every change
+    Any,
+
+    /// A browser showing the page at this path, like `guide/setup.html`
+    Page(&'a str),
+
+    /// A WebSocket client that hasn't said which page it shows yet, which
+    /// only hears about changes affecting every page
+    Unknown,
+}
+
+/// Message sent by the live reload script over a WebSocket
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ClientMessage {
+    /// The page the browser is showing and how far down it is scrolled
+    Visit { page: String, scroll: u64 },
+}
+
+impl LiveEvent {
+    /// The message for `viewer`, or `None` if the event doesn't affect it.
+    /// Only a browser showing the changed page is sent its new body.
+    pub fn message_for(&self, viewer: Viewer) -> Option<LiveMessage> {
+        let page = match viewer {
+            Viewer::Page(page) => Some(page),
+            Viewer::Any | Viewer::Unknown => None,
+        };
+        match self {
+            LiveEvent::Building => Some(LiveMessage::BuildStatus(BuildStatus::Building)),
+            LiveEvent::Changed(changes) => {
+                let affecting: Vec<&Change> = changes
+                    .iter()
+                    .filter(|change| match viewer {
+                        Viewer::Any => true,
+                        Viewer::Page(page) => change.affects(page),
+                        Viewer::Unknown => change.affects_every_page(),
+                    })
+                    .collect();
+                match affecting[..] {
+                    [] => None,
+                    [change] if page.is_some() && change.url.as_deref() == page => {
+                        Some(change.message())
+                    }
+                    [change] => Some(LiveMessage::Reload {
+                        url: change.url.clone(),
+                        scroll: None,
+                    }),
+                    // One message per batch: a patch if this page was re-rendered
+                    // and the rest only changed other pages, whose navigation
+                    // links are in the patch, otherwise a single reload
+                    _ => {
+                        let own = affecting
+                            .iter()
+                            .find(|change| page.is_some() && change.url.as_deref() == page);
+                        match own {
+                            Some(own)
+                                if own.body.is_some()
+                                    && affecting.iter().all(|change| change.url.is_some()) =>
+                            {
+                                Some(own.message())
+                            }
+                            _ => Some(LiveMessage::Reload {
+                                url: None,
+                                scroll: None,
+                            }),
+                        }
+                    }
+                }
+            }
+            // Errors outside of pages, like a failed asset copy, are shown everywhere
+            LiveEvent::Error(error) => match (viewer, error.url.as_deref()) {
+                (Viewer::Any, _) | (_, None) => Some(LiveMessage::Error(error.clone())),
+                (Viewer::Page(page), Some(url)) if url == page => {
+                    Some(LiveMessage::Error(error.clone()))
+                }
+                _ => None,
+            },
+            LiveEvent::Built { rendered, duration } => {
+                Some(LiveMessage::BuildStatus(BuildStatus::Done {
+                    rendered: *rendered,
+                    duration_ms: duration.as_millis() as u64,
+                }))
+            }
+        }
+    }
+}
+
+impl BuildError {
+    /// Describes `error`, which happened while building the file at `path`
+    pub fn new(
+        path: &Path,
+        content_dir: &Path,
+        url: Option<String>,
+        error: &anyhow::Error,
+    ) -> Self {
+        let file = path.strip_prefix(content_dir).unwrap_or(path);
+        Self {
+            file: file.to_string_lossy().into_owned(),
+            url,
+            message: error.to_string(),
+            context: error.chain().skip(1).map(ToString::to_string).collect(),
+        }
+    }
+}
+
+impl Change {
+    /// A change to a file other than a page, which any page might use
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            url: None,
+            body: None,
+            pages_changed: false,
+        }
+    }
+
+    /// A change to the page served at `url`, re-rendered to `html` unless it
+    /// was removed or failed to render
+    pub fn page(path: PathBuf, url: String, html: Option<&str>, pages_changed: bool) -> Self {
+        Self {
+            path,
+            url: Some(url),
+            body: html.and_then(body_fragment).map(str::to_string),
+            pages_changed,
+        }
+    }
+
+    /// Whether a browser showing the page at `page` needs to hear about this change
+    pub fn affects(&self, page: &str) -> bool {
+        self.affects_every_page() || self.url.as_deref() == Some(page)
+    }
+
+    /// Whether this change matters to every page, whichever one is shown
+    pub fn affects_every_page(&self) -> bool {
+        // Assets may be used anywhere, and every page lists the others
+        self.url.is_none() || self.pages_changed
+    }
+
+    /// The message that tells browsers about this change
+    pub fn message(&self) -> LiveMessage {
+        match (&self.url, &self.body) {
+            (Some(url), Some(body)) => LiveMessage::Patch {
+                url: url.clone(),
+                body: body.clone(),
+            },
+            (url, _) => LiveMessage::Reload {
+                url: url.clone(),
+                scroll: None,
+            },
+        }
+    }
+}
+
+/// Returns the inner HTML of a document's `<body>` element
+pub fn body_fragment(html: &str) -> Option<&str> {
+    // Scripts in the head may mention the tag, so only look past it
+    let head_end = html.find("</head>").unwrap_or(0);
+    let open = head_end + html[head_end..].find("<body")?;
+    let start = open + html[open..].find('>')? + 1;
+    let end = html.rfind("</body>")?;
+    html.get(start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_body_fragment() {
+        let html = "<html><head><script>'<body>'</script></head>\n<body class=\"page\">\n<p>Hi</p>\n</body>\n</html>";
+        assert_eq!(body_fragment(html), Some("\n<p>Hi</p>\n"));
+        assert_eq!(body_fragment("<p>No body</p>"), None);
+        assert_eq!(body_fragment("</body><body>"), None);
+    }
+
+    #[test]
+    fn test_message() {
+        let path = PathBuf::from("/docs/page.md");
+        assert_eq!(
+            Change::new(path.clone()).message(),
+            LiveMessage::Reload {
+                url: None,
+                scroll: None
+            }
+        );
+        assert_eq!(
+            Change::page(path.clone(), "page.html".to_string(), None, true).message(),
+            LiveMessage::Reload {
+                url: Some("page.html".to_string()),
+                scroll: None
+            }
+        );
+
+        let change = Change::page(
+            path,
+            "page.html".to_string(),
+            Some("<body>\n<h1>Page</h1>\n</body>"),
+            false,
+        );
+        assert_eq!(
+            serde_json::to_value(change.message()).unwrap(),
+            serde_json::json!({
+                "type": "patch",
+                "url": "page.html",
+                "body": "\n<h1>Page</h1>\n",
+            })
+        );
+    }
+
+    #[test]
+    fn test_affects() {
+        let path = PathBuf::from("/docs/guide/setup.md");
+        let url = "guide/setup.html".to_string();
+
+        let edit = Change::page(path.clone(), url.clone(), None, false);
+        assert!(edit.affects("guide/setup.html"));
+        assert!(!edit.affects("guide/index.html"));
+        assert!(!edit.affects("index.html"));
+
+        // New or removed pages change every page's navigation
+        let added = Change::page(path, url, None, true);
+        assert!(added.affects("index.html"));
+        assert!(added.affects("other.html"));
+
+        assert!(Change::new(PathBuf::from("/docs/logo.png")).affects("other.html"));
+    }
+
+    #[test]
+    fn test_event_messages() {
+        let change = Change::page(
+            PathBuf::from("/docs/a.md"),
+            "a.html".to_string(),
+            None,
+            false,
+        );
+        let event = LiveEvent::Changed(vec![change.clone()]);
+        assert_eq!(event.message_for(Viewer::Any), Some(change.message()));
+        assert_eq!(
+            event.message_for(Viewer::Page("a.html")),
+            Some(change.message())
+        );
+        assert_eq!(event.message_for(Viewer::Page("b.html")), None);
+        assert_eq!(event.message_for(Viewer::Unknown), None);
+
+        // Only the changed page is patched; others that list it reload
+        let added = LiveEvent::Changed(vec![Change::page(
+            PathBuf::from("/docs/a.md"),
+            "a.html".to_string(),
+            Some("<body>A</body>"),
+            true,
+        )]);
+        assert!(matches!(
+            added.message_for(Viewer::Page("a.html")),
+            Some(LiveMessage::Patch { .. })
+        ));
+        let reload = Some(LiveMessage::Reload {
+            url: Some("a.html".to_string()),
+            scroll: None,
+        });
+        assert_eq!(added.message_for(Viewer::Page("b.html")), reload);
+        assert_eq!(added.message_for(Viewer::Unknown), reload);
+        assert_eq!(added.message_for(Viewer::Any), reload);
+
+        // Build status goes to every page
+        assert!(LiveEvent::Building
+            .message_for(Viewer::Page("b.html"))
+            .is_some());
+        assert!(LiveEvent::Building.message_for(Viewer::Unknown).is_some());
+        let built = LiveEvent::Built {
+            rendered: 3,
+            duration: Duration::from_millis(42),
+        };
+        assert_eq!(
+            serde_json::to_value(built.message_for(Viewer::Page("b.html"))).unwrap(),
+            serde_json::json!({
+                "type": "build-status",
+                "state": "done",
+                "rendered": 3,
+                "duration_ms": 42,
+            })
+        );
+    }
+
+    #[test]
+    fn test_client_message() {
+        let message: ClientMessage =
+            serde_json::from_str(r#"{"type":"visit","page":"guide/index.html","scroll":120}"#)
+                .unwrap();
+        assert_eq!(
+            message,
+            ClientMessage::Visit {
+                page: "guide/index.html".to_string(),
+                scroll: 120,
+            }
+        );
+        assert!(serde_json::from_str::<ClientMessage>(r#"{"type":"unknown"}"#).is_err());
+    }
+
+    #[test]
+    fn test_build_error() {
+        let error = anyhow::anyhow!("invalid type: found a sequence")
+            .context("Invalid YAML front matter")
+            .context("Failed to parse front matter: /docs/guide/a.md");
+        let error = BuildError::new(
+            Path::new("/docs/guide/a.md"),
+            Path::new("/docs"),
+            Some("guide/a.html".to_string()),
+            &error,
+        );
+        assert_eq!(error.file, "guide/a.md");
+        assert_eq!(
+            error.message,
+            "Failed to parse front matter: /docs/guide/a.md"
+        );
+        assert_eq!(
+            error.context,
+            [
+                "Invalid YAML front matter",
+                "invalid type: found a sequence"
+            ]
+        );
+
+        let event = LiveEvent::Error(error);
+        assert!(event.message_for(Viewer::Page("guide/a.html")).is_some());
+        assert!(event.message_for(Viewer::Page("index.html")).is_none());
+        assert!(event.message_for(Viewer::Unknown).is_none());
+        assert_eq!(
+            serde_json::to_value(event.message_for(Viewer::Any)).unwrap()["type"],
+            "error"
+        );
+    }
+
+    #[test]
+    fn test_batch_sends_one_message() {
+        let page = |name: &str, html: Option<&str>| {
+            Change::page(
+                PathBuf::from(format!("/docs/{}.md", name)),
+                format!("{}.html", name),
+                html,
+                true,
+            )
+        };
+        let batch = LiveEvent::Changed(vec![
+            page("a", Some("<body>A</body>")),
+            page("b", Some("<body>B</body>")),
+        ]);
+
+        // Both pages changed the navigation, so each is patched with its own body
+        assert_eq!(
+            batch.message_for(Viewer::Page("a.html")),
+            Some(LiveMessage::Patch {
+                url: "a.html".to_string(),
+                body: "A".to_string()
+            })
+        );
+        let reload = Some(LiveMessage::Reload {
+            url: None,
+            scroll: None,
+        });
+        assert_eq!(batch.message_for(Viewer::Page("c.html")), reload);
+        assert_eq!(batch.message_for(Viewer::Any), reload);
+
+        // Assets may be on any page, so they need a reload
+        let with_asset = LiveEvent::Changed(vec![
+            page("a", Some("<body>A</body>")),
+            Change::new(PathBuf::from("/docs/logo.png")),
+        ]);
+        assert_eq!(with_asset.message_for(Viewer::Page("a.html")), reload);
+    }
+}
@@ -1,16 +1,45 @@
 use anyhow::Result;
-use live_md::{config::Config, server::start_server};
+use clap::Parser;
+use live_md::{
+    assets::copy_all_assets,
+    cli::{Cli, Command},
+    render_all_markdown_files,
+    server::start_server,
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Use default configuration
-    let config = Config::default();
+    let cli = Cli::parse();
+    let config = cli.to_config()?;
 
-    // Create output directory if it doesn't exist
-    std::fs::create_dir_all(&config.output_dir)?;
+    match cli.command() {
+        Command::Serve => start_server(config).await?,
+        Command::Build => {
+            // Create output directory if it doesn't exist
+            std::fs::create_dir_all(&config.output_dir)?;
 
-    // Start server with default configuration
-    start_server(config).await?;
+            let stats = render_all_markdown_files(
+                &config.content_dir,
+                &config.output_dir,
+                &config.markdown,
+                &config.filter,
+                &config.templates()?,
+                false,
+            )?;
+            copy_all_assets(
+                &config.content_dir,
+                &config.output_dir,
+                &config.markdown,
+                &config.filter,
+            )?;
+            println!(
+                "Rendered {} files to {}",
+                stats.files.len(),
+                config.output_dir.display()
+            );
+            println!("{}", stats);
+        }
+    }
 
     Ok(())
 }
@@ -1,21 +1,86 @@
+use crate::{
+    cache,
+    front_matter::{parse_front_matter, split_front_matter, PageMeta},
+    highlight::{self, CodeBlockInfo},
+    nav::{output_file_name, split_order_prefix},
+    templates::{PageContext, Templates},
+    toc::{table_of_contents, Heading, Slugger},
+};
 use anyhow::{Context, Result};
-use pulldown_cmark::{html, Options, Parser};
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, HeadingLevel, Options, Parser, Tag};
+use serde::{Deserialize, Serialize};
 use std::{
     fs,
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 
+/// File extensions recognized as markdown unless configured otherwise
+pub const DEFAULT_EXTENSIONS: [&str; 5] = ["md", "markdown", "mdown", "mkd", "mdx"];
+
 /// Configuration for markdown parsing
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct MarkdownOptions {
     pub disable_tables: bool,
     pub disable_footnotes: bool,
     pub disable_strikethrough: bool,
     pub disable_tasklists: bool,
     pub disable_smart_punctuation: bool,
+
+    /// Render fenced code blocks without syntax highlighting
+    pub disable_highlighting: bool,
+
+    /// Number the lines of every highlighted code block
+    pub line_numbers: bool,
+
+    /// Highlighting theme for light mode
+    pub highlight_theme_light: Option<String>,
+
+    /// Highlighting theme for dark mode
+    pub highlight_theme_dark: Option<String>,
+
+    /// File extensions of markdown pages, without the dot. Matched ignoring case.
+    pub extensions: Vec<String>,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        Self {
+            disable_tables: false,
+            disable_footnotes: false,
+            disable_strikethrough: false,
+            disable_tasklists: false,
+            disable_smart_punctuation: false,
+            disable_highlighting: false,
+            line_numbers: false,
+            highlight_theme_light: None,
+            highlight_theme_dark: None,
+            extensions: DEFAULT_EXTENSIONS.map(String::from).to_vec(),
+        }
+    }
 }
 
 impl MarkdownOptions {
+    /// Checks whether a file is a markdown page by its extension.
+    ///
+    /// Scanning, watching and link rewriting all go through this, so a file
+    /// is treated as a page everywhere or nowhere.
+    pub fn is_markdown(&self, path: &Path) -> bool {
+        self.extension_rank(path).is_some()
+    }
+
+    /// Position of a markdown file's extension among the configured ones.
+    ///
+    /// When two files in a directory render to the same page, the one whose
+    /// extension comes first is used.
+    pub fn extension_rank(&self, path: &Path) -> Option<usize> {
+        let ext = path.extension()?.to_str()?;
+        self.extensions
+            .iter()
+            .position(|known| known.eq_ignore_ascii_case(ext))
+    }
+
     fn to_parser_options(&self) -> Options {
         let mut options = Options::all();
         if self.disable_tables {
@@ -35,22 +100,93 @@ impl MarkdownOptions {
         }
         options
     }
-}
 
-/// Renders a markdown file to HTML and saves it to the output directory
-pub fn render_markdown_file(markdown_path: &Path, output_dir: &Path) -> Result<PathBuf> {
-    // Read markdown content
-    let markdown_content = fs::read_to_string(markdown_path)
-        .with_context(|| format!("Failed to read markdown file: {}", markdown_path.display()))?;
+    /// The highlighting theme used in light mode
+    pub fn light_theme(&self) -> &str {
+        self.highlight_theme_light
+            .as_deref()
+            .unwrap_or(highlight::DEFAULT_LIGHT_THEME)
+    }
+
+    /// The highlighting theme used in dark mode
+    pub fn dark_theme(&self) -> &str {
+        self.highlight_theme_dark
+            .as_deref()
+            .unwrap_or(highlight::DEFAULT_DARK_THEME)
+    }
 
-    // Generate HTML content with default options
-    let html_content = markdown_to_html(&markdown_content, &MarkdownOptions::default());
+    /// Identifies the options, changing whenever one that affects rendered
+    /// pages does. Each option is hashed explicitly, so unlike their `Debug`
+    /// form the result is stable across builds.
+    pub fn fingerprint(&self) -> String {
+        // Listing every field makes adding an option a compile error here
+        let Self {
+            disable_tables,
+            disable_footnotes,
+            disable_strikethrough,
+            disable_tasklists,
+            disable_smart_punctuation,
+            disable_highlighting,
+            line_numbers,
+            highlight_theme_light: _,
+            highlight_theme_dark: _,
+            extensions,
+        } = self;
+        let flags = [
+            *disable_tables,
+            *disable_footnotes,
+            *disable_strikethrough,
+            *disable_tasklists,
+            *disable_smart_punctuation,
+            *disable_highlighting,
+            *line_numbers,
+        ]
+        .map(u8::from);
+        let themes = [self.light_theme(), self.dark_theme()];
+        cache::fingerprint(
+            [flags.as_slice()]
+                .into_iter()
+                .chain(themes.map(str::as_bytes))
+                .chain(extensions.iter().map(String::as_bytes)),
+        )
+    }
 
-    // Generate full HTML document
-    let final_html = wrap_html_template(&html_content, markdown_path)?;
+    /// Stylesheet for highlighted code, or nothing when highlighting is off
+    pub fn highlight_css(&self) -> Result<String> {
+        if self.disable_highlighting {
+            return Ok(String::new());
+        }
+        highlight::theme_css(self.light_theme(), self.dark_theme())
+    }
+}
 
-    // Determine output path"
-    let output_path = get_output_path(markdown_path, output_dir)?;
+/// Renders a markdown file to HTML and saves it to the output directory,
+/// at the same relative path it has in the content directory.
+///
+/// `nav` is the site navigation to include on the page, if any.
+pub fn render_markdown_file(
+    markdown_path: &Path,
+    content_dir: &Path,
+    output_dir: &Path,
+    options: &MarkdownOptions,
+    templates: &Templates,
+    nav: &str,
+    live_reload: bool,
+) -> Result<PathBuf> {
+    let final_html = render_page_html(markdown_path, options, templates, nav, live_reload)?;
+    write_page_html(markdown_path, content_dir, output_dir, options, &final_html)
+}
+
+/// Saves a page's HTML to the output directory, returning where it was written
+pub fn write_page_html(
+    markdown_path: &Path,
+    content_dir: &Path,
+    output_dir: &Path,
+    options: &MarkdownOptions,
+    html: &str,
+) -> Result<PathBuf> {
+    // Determine output path
+    let output_path = get_output_path(markdown_path, content_dir, output_dir, options)?;
 
     // Ensure parent directory exists
     if let Some(parent) = output_path.parent() {
@@ -59,64 +195,415 @@ pub fn render_markdown_file(markdown_path: &Path, output_dir: &Path) -> Result<P
     }
 
     // Write HTML file
-    fs::write(&output_path, final_html)
+    fs::write(&output_path, html)
         .with_context(|| format!("Failed to write HTML file: {}", output_path.display()))?;
 
     Ok(output_path)
 }
 
+/// Renders a markdown file to a complete HTML page with its template
+pub fn render_page_html(
+    markdown_path: &Path,
+    options: &MarkdownOptions,
+    templates: &Templates,
+    nav: &str,
+    live_reload: bool,
+) -> Result<String> {
+    let content = render_page_content(markdown_path, options)?;
+    let styles = options.highlight_css()?;
+    layout_page(
+        markdown_path,
+        &content,
+        templates,
+        nav,
+        &styles,
+        live_reload,
+    )
+}
+
+/// A page's markdown rendered to HTML, kept apart from the navigation and
+/// template it is laid out with so that those can change without the
+/// markdown being rendered again
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PageContent {
+    /// The page title
+    pub title: String,
+
+    /// The rendered markdown
+    pub html: String,
+
+    /// Table of contents, empty unless the front matter asks for one
+    pub toc: String,
+
+    /// Metadata from the page's front matter
+    pub meta: PageMeta,
+
+    /// When the page's source was last changed
+    pub last_modified: Option<SystemTime>,
+}
+
+/// Renders the markdown of a file, without laying it out as a page
+pub fn render_page_content(markdown_path: &Path, options: &MarkdownOptions) -> Result<PageContent> {
+    // Read markdown content
+    let markdown_content = fs::read_to_string(markdown_path)
+        .with_context(|| format!("Failed to read markdown file: {}", markdown_path.display()))?;
+
+    // Separate the front matter from the markdown body
+    let (meta, body) = parse_front_matter(&markdown_content)
+        .with_context(|| format!("Failed to parse front matter: {}", markdown_path.display()))?;
+
+    // Generate HTML content
+    let rendered = render_markdown(body, markdown_path.parent(), options);
+
+    let toc = if meta.toc {
+        table_of_contents(&rendered.headings)
+    } else {
+        String::new()
+    };
+    Ok(PageContent {
+        title: page_title(&meta, body, markdown_path),
+        html: rendered.html,
+        toc,
+        meta,
+        last_modified: last_modified(markdown_path),
+    })
+}
+
+/// Lays out the rendered content of the markdown file at `markdown_path` as a
+/// complete HTML page with its template, the site navigation and `styles`,
+/// the stylesheet from [`MarkdownOptions::highlight_css`]
+pub fn layout_page(
+    markdown_path: &Path,
+    content: &PageContent,
+    templates: &Templates,
+    nav: &str,
+    styles: &str,
+    live_reload: bool,
+) -> Result<String> {
+    templates
+        .render_page(&PageContext {
+            title: &content.title,
+            content: &content.html,
+            toc: &content.toc,
+            nav,
+            styles,
+            meta: &content.meta,
+            last_modified: content.last_modified,
+            live_reload,
+        })
+        .with_context(|| format!("Failed to render page: {}", markdown_path.display()))
+}
+
+/// When a file was last modified, if the file system knows
+pub fn last_modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Removes the rendered HTML for a markdown file that was deleted or renamed away
+pub fn remove_rendered_file(
+    markdown_path: &Path,
+    content_dir: &Path,
+    output_dir: &Path,
+    options: &MarkdownOptions,
+) -> Result<()> {
+    let output_path = get_output_path(markdown_path, content_dir, output_dir, options)?;
+
+    match fs::remove_file(&output_path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => {
+            Err(e).with_context(|| format!("Failed to remove HTML file: {}", output_path.display()))
+        }
+    }
+}
+
+/// Markdown rendered to HTML, along with the headings it contains
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedMarkdown {
+    pub html: String,
+    pub headings: Vec<Heading>,
+}
+
 /// Converts markdown text to HTML with specified options
+///
+/// Links to other markdown files are rewritten to point at their rendered HTML,
+/// and fenced code blocks are syntax highlighted unless highlighting is disabled.
 pub fn markdown_to_html(markdown: &str, options: &MarkdownOptions) -> String {
-    let parser = Parser::new_ext(markdown, options.to_parser_options());
-    let mut html_output = String::with_capacity(markdown.len() * 2);
-    html::push_html(&mut html_output, parser);
-    html_output
+    render_markdown(markdown, None, options).html
 }
 
-/// Wraps HTML content in a complete HTML document with styling
-fn wrap_html_template(content: &str, source_path: &Path) -> Result<String> {
-    let title = source_path
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("Markdown Preview")
-        .replace('_', " ");
+/// Converts markdown text to HTML like [`markdown_to_html`], collecting the headings.
+///
+/// Every heading gets a unique `id` and an anchor link to itself, and a
+/// paragraph containing only `[[toc]]` is replaced by a table of contents.
+/// Relative links are resolved from `source_dir`, the directory of the
+/// markdown file, when it is known.
+pub fn render_markdown(
+    markdown: &str,
+    source_dir: Option<&Path>,
+    options: &MarkdownOptions,
+) -> RenderedMarkdown {
+    let mut events = Vec::new();
+    let mut headings = Vec::new();
+    let mut slugger = Slugger::default();
+
+    // Fenced code block being collected: its info string and text so far
+    let mut code_block: Option<(CodeBlockInfo, String)> = None;
+    // Heading being collected: its start tag and the events inside it
+    let mut heading: Option<(Tag, Vec<Event>)> = None;
 
-    Ok(crate::wrap_html_template(content, &title))
+    for event in Parser::new_ext(markdown, options.to_parser_options()) {
+        let event = match event {
+            Event::Start(Tag::Link(link_type, dest, title)) => {
+                let dest =
+                    rewrite_markdown_link(&dest, source_dir, options).map_or(dest, CowStr::from);
+                Event::Start(Tag::Link(link_type, dest, title))
+            }
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info)))
+                if !options.disable_highlighting =>
+            {
+                code_block = Some((CodeBlockInfo::parse(&info), String::new()));
+                continue;
+            }
+            Event::Text(text) if code_block.is_some() => {
+                if let Some((_, code)) = &mut code_block {
+                    code.push_str(&text);
+                }
+                continue;
+            }
+            Event::End(Tag::CodeBlock(_)) if code_block.is_some() => {
+                let Some((info, code)) = code_block.take() else {
+                    continue;
+                };
+                let html = highlight::highlight_code_block(&code, &info, options.line_numbers);
+                Event::Html(html.into())
+            }
+            Event::Start(tag @ Tag::Heading(..)) => {
+                heading = Some((tag, Vec::new()));
+                continue;
+            }
+            Event::End(Tag::Heading(..)) if heading.is_some() => {
+                let Some((Tag::Heading(level, id, classes), inner)) = heading.take() else {
+                    continue;
+                };
+                let text = heading_text(&inner);
+                let id = match id {
+                    Some(id) => {
+                        slugger.reserve(id);
+                        id.to_string()
+                    }
+                    None => slugger.slug(&text),
+                };
+
+                let mut start = format!("<{} id=\"{}\"", level, crate::escape_html(&id));
+                if !classes.is_empty() {
+                    start.push_str(&format!(
+                        " class=\"{}\"",
+                        crate::escape_html(&classes.join(" "))
+                    ));
+                }
+                start.push('>');
+                events.push(Event::Html(start.into()));
+                events.extend(inner);
+                events.push(Event::Html(
+                    format!(
+                        "<a class=\"anchor\" href=\"#{}\" aria-hidden=\"true\">#</a></{}>\n",
+                        crate::escape_html(&id),
+                        level
+                    )
+                    .into(),
+                ));
+
+                headings.push(Heading {
+                    level: level as u32,
+                    id,
+                    text,
+                });
+                continue;
+            }
+            _ => event,
+        };
+
+        match &mut heading {
+            Some((_, inner)) => inner.push(event),
+            None => events.push(event),
+        }
+    }
+
+    let mut html = String::with_capacity(markdown.len() * 2);
+    html::push_html(&mut html, events.into_iter());
+
+    if html.contains(TOC_MARKER) {
+        html = html.replace(TOC_MARKER, &table_of_contents(&headings));
+    }
+
+    RenderedMarkdown { html, headings }
 }
 
-/// Determines the output HTML path for a given markdown path
-fn get_output_path(markdown_path: &Path, output_dir: &Path) -> Result<PathBuf> {
-    let file_stem = markdown_path
-        .file_stem()
-        .with_context(|| format!("Invalid markdown path: {}", markdown_path.display()))?;
+/// Rendered form of a paragraph holding only the `[[toc]]` marker
+const TOC_MARKER: &str = "<p>[[toc]]</p>\n";
+
+/// Returns the plain text of a heading's contents
+fn heading_text(events: &[Event]) -> String {
+    let mut text = String::new();
+    for event in events {
+        match event {
+            Event::Text(t) | Event::Code(t) => text.push_str(t),
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            _ => {}
+        }
+    }
+    text.trim().to_string()
+}
 
-    // Handle README.md as a special case
-    if markdown_path.file_name().unwrap_or_default() == "README.md" {
-        return Ok(output_dir.join("index.html"));
+/// Rewrites a link destination that points at a markdown file to the
+/// corresponding HTML file, keeping any query string or fragment.
+///
+/// Returns `None` for destinations that should be left untouched, such as
+/// URLs with a scheme (`https:`, `mailto:`) or links to non-markdown files.
+fn rewrite_markdown_link(
+    dest: &str,
+    source_dir: Option<&Path>,
+    options: &MarkdownOptions,
+) -> Option<String> {
+    if dest.starts_with("//") || has_url_scheme(dest) {
+        return None;
     }
 
-    // Get the parent directory of the markdown file
-    if let Some(parent) = markdown_path.parent() {
-        // Try to find common root directory by walking up the parent directories
-        let mut current = parent;
-        while let Some(parent_dir) = current.parent() {
-            if parent_dir
-                .file_name()
-                .map_or(false, |name| name == "content" || name == "doc")
-            {
-                // Found the content root, now we can get the relative path
-                if let Ok(rel_path) = parent.strip_prefix(parent_dir) {
-                    let output_path = output_dir.join(rel_path);
-                    fs::create_dir_all(&output_path)?;
-                    return Ok(output_path.join(file_stem).with_extension("html"));
+    let suffix_start = dest.find(['?', '#']).unwrap_or(dest.len());
+    let (path, suffix) = dest.split_at(suffix_start);
+
+    let (dir, file_name) = match path.rfind('/') {
+        Some(i) => path.split_at(i + 1),
+        None => ("", path),
+    };
+    let (stem, _) = file_name.rsplit_once('.')?;
+    if stem.is_empty() || !options.is_markdown(Path::new(file_name)) {
+        return None;
+    }
+
+    // A README is its directory's index.html unless an index file is next to
+    // it, which can only be checked for relative links from a known page
+    let html_name = match source_dir.filter(|_| !path.starts_with('/')) {
+        Some(source_dir) => output_file_name(&source_dir.join(path), options)?,
+        None if stem == "README" => "index.html".to_string(),
+        None => format!("{}.html", stem),
+    };
+    Some(format!("{}{}{}", dir, html_name, suffix))
+}
+
+/// Checks whether a link destination starts with a URL scheme like `https:`
+fn has_url_scheme(dest: &str) -> bool {
+    match dest.find(':') {
+        Some(i) => {
+            let scheme = &dest[..i];
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        }
+        None => false,
+    }
+}
+
+/// Resolves the title of a page from, in order of preference, the front matter
+/// `title`, the first level-one heading, or the file name.
+///
+/// Invalid front matter is skipped rather than treated as an error.
+pub fn resolve_title(markdown: &str, source_path: &Path) -> String {
+    match parse_front_matter(markdown) {
+        Ok((meta, body)) => page_title(&meta, body, source_path),
+        Err(_) => page_title(
+            &PageMeta::default(),
+            split_front_matter(markdown).1,
+            source_path,
+        ),
+    }
+}
+
+/// Resolves the title of a page whose front matter has already been parsed
+pub fn page_title(meta: &PageMeta, body: &str, source_path: &Path) -> String {
+    meta.title
+        .clone()
+        .filter(|title| !title.trim().is_empty())
+        .or_else(|| extract_title(body))
+        .unwrap_or_else(|| fallback_title(source_path))
+}
+
+/// Returns the plain text of the first level-one heading
+fn extract_title(markdown: &str) -> Option<String> {
+    let mut in_title = false;
+    let mut title = String::new();
+
+    for event in Parser::new_ext(markdown, Options::empty()) {
+        match event {
+            Event::Start(Tag::Heading(HeadingLevel::H1, ..)) => in_title = true,
+            Event::End(Tag::Heading(HeadingLevel::H1, ..)) => {
+                let trimmed = title.trim();
+                if !trimmed.is_empty() {
+                    return Some(trimmed.to_string());
                 }
+                in_title = false;
+                title.clear();
             }
-            current = parent_dir;
+            Event::Text(text) | Event::Code(text) if in_title => title.push_str(&text),
+            Event::SoftBreak | Event::HardBreak if in_title => title.push(' '),
+            _ => {}
         }
     }
 
-    // If no content root found or other issues, place in output root
-    Ok(output_dir.join(file_stem).with_extension("html"))
+    None
+}
+
+/// Derives a title from the file name when the page doesn't provide one
+pub fn fallback_title(source_path: &Path) -> String {
+    source_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .map(|stem| crate::format_display_name(split_order_prefix(stem).1))
+        .unwrap_or_else(|| "Markdown Preview".to_string())
+}
+
+/// Determines the output HTML path for a given markdown path, mirroring its
+/// location in the content directory
+pub(crate) fn get_output_path(
+    markdown_path: &Path,
+    content_dir: &Path,
+    output_dir: &Path,
+    options: &MarkdownOptions,
+) -> Result<PathBuf> {
+    let file_name = output_file_name(markdown_path, options)
+        .with_context(|| format!("Invalid markdown path: {}", markdown_path.display()))?;
+    let rel_dir = relative_dir(markdown_path, content_dir)?;
+    Ok(output_dir.join(rel_dir).join(file_name))
+}
+
+/// Finds the directory of a markdown file relative to the content directory.
+///
+/// Either path may go through a symlink, so when they don't share a prefix
+/// as given they are compared in resolved form. Only the parent directory
+/// is resolved, since the file itself may have been deleted.
+fn relative_dir(markdown_path: &Path, content_dir: &Path) -> Result<PathBuf> {
+    let parent = markdown_path.parent().unwrap_or(Path::new(""));
+    if let Ok(rel_dir) = parent.strip_prefix(content_dir) {
+        return Ok(rel_dir.to_path_buf());
+    }
+
+    let not_in_content_dir = || {
+        format!(
+            "Markdown file is not in the content directory {}: {}",
+            content_dir.display(),
+            markdown_path.display()
+        )
+    };
+    let content_dir = content_dir
+        .canonicalize()
+        .with_context(not_in_content_dir)?;
+    let parent = parent.canonicalize().with_context(not_in_content_dir)?;
+    parent
+        .strip_prefix(&content_dir)
+        .map(Path::to_path_buf)
+        .with_context(not_in_content_dir)
 }
 
 #[cfg(test)]
@@ -129,7 +616,7 @@ mod tests {
         let options = MarkdownOptions::default();
         let markdown = "# Hello\n\nThis is a **test**";
         let html = markdown_to_html(markdown, &options);
-        assert!(html.contains("<h1>Hello</h1>"));
+        assert!(html.contains("<h1 id=\"hello\">Hello<a class=\"anchor\" href=\"#hello\""));
         assert!(html.contains("<strong>test</strong>"));
     }
 
@@ -143,6 +630,30 @@ mod tests {
         assert!(html.contains("<td>Cell</td>"));
     }
 
+    #[test]
+    fn test_is_markdown() {
+        let options = MarkdownOptions::default();
+        for file in [
+            "page.md",
+            "a/notes.markdown",
+            "README.MD",
+            "b.Mdown",
+            "c.mdx",
+        ] {
+            assert!(options.is_markdown(Path::new(file)), "{}", file);
+        }
+        for file in ["logo.png", "md", "notes.md.bak", ".md"] {
+            assert!(!options.is_markdown(Path::new(file)), "{}", file);
+        }
+
+        let options = MarkdownOptions {
+            extensions: vec!["txt".to_string()],
+            ..MarkdownOptions::default()
+        };
+        assert!(options.is_markdown(Path::new("notes.TXT")));
+        assert!(!options.is_markdown(Path::new("page.md")));
+    }
+
     #[test]
     fn test_render_markdown_file() -> Result<()> {
         let temp_dir = TempDir::new()?;
@@ -157,12 +668,20 @@ mod tests {
         fs::write(&markdown_path, "# Test Heading\n\nTest content")?;
 
         // Render the file
-        let output_path = render_markdown_file(&markdown_path, &output_dir)?;
+        let output_path = render_markdown_file(
+            &markdown_path,
+            &content_dir,
+            &output_dir,
+            &MarkdownOptions::default(),
+            &Templates::default(),
+            "",
+            true,
+        )?;
 
         // Verify the output
         assert!(output_path.exists());
         let html_content = fs::read_to_string(output_path)?;
-        assert!(html_content.contains("<h1>Test Heading</h1>"));
+        assert!(html_content.contains("<h1 id=\"test-heading\">Test Heading"));
         assert!(html_content.contains("Test content"));
         assert!(html_content.contains("<!DOCTYPE html>"));
 
@@ -183,7 +702,15 @@ mod tests {
         fs::write(&readme_path, "# Project README")?;
 
         // Render the file
-        let output_path = render_markdown_file(&readme_path, &output_dir)?;
+        let output_path = render_markdown_file(
+            &readme_path,
+            &content_dir,
+            &output_dir,
+            &MarkdownOptions::default(),
+            &Templates::default(),
+            "",
+            true,
+        )?;
 
         // Verify it was rendered as index.html
         assert_eq!(output_path.file_name().unwrap(), "index.html");
@@ -200,14 +727,21 @@ mod tests {
         let nested_dir = content_dir.join("docs").join("section");
 
         fs::create_dir_all(&nested_dir)?;
-        std::env::set_current_dir(&temp_dir)?;
 
         // Create a nested markdown file
         let markdown_path = nested_dir.join("nested.md");
         fs::write(&markdown_path, "# Nested Content")?;
 
         // Render the file
-        let output_path = render_markdown_file(&markdown_path, &output_dir)?;
+        let output_path = render_markdown_file(
+            &markdown_path,
+            &content_dir,
+            &output_dir,
+            &MarkdownOptions::default(),
+            &Templates::default(),
+            "",
+            true,
+        )?;
 
         // Verify directory structure is preserved
         assert!(output_path.starts_with(&output_dir));
@@ -218,6 +752,115 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_output_path_mirrors_content_dir() -> Result<()> {
+        let options = MarkdownOptions::default();
+        let temp_dir = TempDir::new()?;
+        let output_dir = temp_dir.path().join("output");
+
+        for name in ["docs", "notes", "content", "doc", "my site", "content/doc"] {
+            let content_dir = temp_dir.path().join(name);
+            for (source, expected) in [
+                ("page.md", "page.html"),
+                ("a/page.md", "a/page.html"),
+                ("a/doc/page.md", "a/doc/page.html"),
+                ("content/b/page.md", "content/b/page.html"),
+                ("a/README.md", "a/index.html"),
+                ("a/notes.markdown", "a/notes.html"),
+                ("a/README.MD", "a/index.html"),
+                ("a/release-1.2.md", "a/release-1.2.html"),
+            ] {
+                assert_eq!(
+                    get_output_path(
+                        &content_dir.join(source),
+                        &content_dir,
+                        &output_dir,
+                        &options
+                    )?,
+                    output_dir.join(expected),
+                    "{} in {}",
+                    source,
+                    name
+                );
+            }
+        }
+
+        // Files outside the content directory have no output path
+        let content_dir = temp_dir.path().join("docs");
+        assert!(get_output_path(
+            &temp_dir.path().join("page.md"),
+            &content_dir,
+            &output_dir,
+            &options
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_path_with_symlinked_root() -> Result<()> {
+        let options = MarkdownOptions::default();
+        let temp_dir = TempDir::new()?;
+        let real_dir = temp_dir.path().join("real");
+        let link_dir = temp_dir.path().join("link");
+        let output_dir = temp_dir.path().join("output");
+        fs::create_dir_all(real_dir.join("nested"))?;
+        std::os::unix::fs::symlink(&real_dir, &link_dir)?;
+        fs::write(real_dir.join("nested").join("page.md"), "# Page")?;
+
+        let expected = output_dir.join("nested").join("page.html");
+        for (content_dir, source_root) in [
+            (&link_dir, &real_dir),
+            (&real_dir, &link_dir),
+            (&link_dir, &link_dir),
+        ] {
+            let source = source_root.join("nested").join("page.md");
+            assert_eq!(
+                get_output_path(&source, content_dir, &output_dir, &options)?,
+                expected
+            );
+        }
+
+        // A deleted file still maps to its old output
+        let source = link_dir.join("nested").join("gone.md");
+        assert_eq!(
+            get_output_path(&source, &real_dir, &output_dir, &options)?,
+            output_dir.join("nested").join("gone.html")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_rendered_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content_dir = temp_dir.path().join("content");
+        let output_dir = temp_dir.path().join("output");
+        fs::create_dir_all(&content_dir)?;
+
+        let options = MarkdownOptions::default();
+        let markdown_path = content_dir.join("gone.md");
+        fs::write(&markdown_path, "# Gone")?;
+        let output_path = render_markdown_file(
+            &markdown_path,
+            &content_dir,
+            &output_dir,
+            &options,
+            &Templates::default(),
+            "",
+            true,
+        )?;
+        assert!(output_path.exists());
+
+        fs::remove_file(&markdown_path)?;
+        remove_rendered_file(&markdown_path, &content_dir, &output_dir, &options)?;
+        assert!(!output_path.exists());
+
+        // Already removed
+        remove_rendered_file(&markdown_path, &content_dir, &output_dir, &options)?;
+        Ok(())
+    }
+
     #[test]
     fn test_markdown_special_features() {
         let mut options = MarkdownOptions::default();
@@ -245,6 +888,194 @@ mod tests {
         assert!(!markdown_to_html(table, &options).contains("<table>"));
     }
 
+    #[test]
+    fn test_rewrite_markdown_link() {
+        let options = MarkdownOptions::default();
+        let rewrite = |dest| rewrite_markdown_link(dest, None, &options);
+        assert_eq!(rewrite("setup.md").as_deref(), Some("setup.html"));
+        assert_eq!(
+            rewrite("../other/page.md#section").as_deref(),
+            Some("../other/page.html#section")
+        );
+        assert_eq!(
+            rewrite("guide/intro.md?raw=1#top").as_deref(),
+            Some("guide/intro.html?raw=1#top")
+        );
+        assert_eq!(rewrite("README.md").as_deref(), Some("index.html"));
+        assert_eq!(
+            rewrite("docs/README.md#usage").as_deref(),
+            Some("docs/index.html#usage")
+        );
+        assert_eq!(
+            rewrite("/notes/today.md").as_deref(),
+            Some("/notes/today.html")
+        );
+
+        // Every configured extension counts, whatever its case
+        assert_eq!(rewrite("notes.markdown").as_deref(), Some("notes.html"));
+        assert_eq!(
+            rewrite("guide/README.MD").as_deref(),
+            Some("guide/index.html")
+        );
+        let md_only = MarkdownOptions {
+            extensions: vec!["md".to_string()],
+            ..MarkdownOptions::default()
+        };
+        assert_eq!(
+            rewrite_markdown_link("notes.markdown", None, &md_only),
+            None
+        );
+
+        // Left untouched
+        assert_eq!(rewrite("https://example.com/setup.md"), None);
+        assert_eq!(rewrite("//example.com/setup.md"), None);
+        assert_eq!(rewrite("mailto:someone@example.com"), None);
+        assert_eq!(rewrite("image.png"), None);
+        assert_eq!(rewrite("#section"), None);
+        assert_eq!(rewrite("notes.md.bak"), None);
+        assert_eq!(rewrite(".md"), None);
+    }
+
+    #[test]
+    fn test_rewrite_readme_link_next_to_index() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_dir = temp_dir.path();
+        fs::create_dir(source_dir.join("guide"))?;
+        fs::write(source_dir.join("index.md"), "# Home")?;
+        fs::write(source_dir.join("README.md"), "# Readme")?;
+        fs::write(source_dir.join("guide").join("README.md"), "# Guide")?;
+
+        // README.md is an ordinary page when index.md takes the directory's index
+        let options = MarkdownOptions::default();
+        let rewrite = |dest| rewrite_markdown_link(dest, Some(source_dir), &options);
+        assert_eq!(rewrite("README.md").as_deref(), Some("README.html"));
+        assert_eq!(rewrite("index.md").as_deref(), Some("index.html"));
+        assert_eq!(
+            rewrite("guide/README.md#top").as_deref(),
+            Some("guide/index.html#top")
+        );
+        assert_eq!(
+            rewrite("release-1.2.md").as_deref(),
+            Some("release-1.2.html")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_markdown_links_rendered_as_html() {
+        let options = MarkdownOptions::default();
+        let markdown = "[setup](setup.md) [ref] [site](https://example.com/a.md)\n\n[ref]: ../guide/README.md#install";
+        let html = markdown_to_html(markdown, &options);
+        assert!(html.contains("href=\"setup.html\""));
+        assert!(html.contains("href=\"../guide/index.html#install\""));
+        assert!(html.contains("href=\"https://example.com/a.md\""));
+    }
+
+    #[test]
+    fn test_resolve_title() {
+        let path = Path::new("docs/getting_started.md");
+
+        assert_eq!(
+            resolve_title("---\ntitle: From YAML\n---\n# Heading\n", path),
+            "From YAML"
+        );
+        assert_eq!(
+            resolve_title("+++\ntitle = \"From TOML\"\n+++\n# Heading\n", path),
+            "From TOML"
+        );
+        assert_eq!(
+            resolve_title(
+                "---\ndate: 2024-11-16\n---\nIntro\n\n# The *real* `title`\n",
+                path
+            ),
+            "The real title"
+        );
+        assert_eq!(resolve_title("Title\n=====\n", path), "Title");
+        assert_eq!(
+            resolve_title("## Only a subheading\n", path),
+            "Getting started"
+        );
+        assert_eq!(resolve_title("#\n\n# Second\n", path), "Second");
+        assert_eq!(
+            resolve_title("---\nweight: heavy\n---\n# After bad front matter\n", path),
+            "After bad front matter"
+        );
+    }
+
+    #[test]
+    fn test_front_matter_is_not_rendered() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let markdown_path = temp_dir.path().join("page.md");
+        let output_dir = temp_dir.path().join("output");
+        fs::write(
+            &markdown_path,
+            "---\ntitle: Meta title\ndescription: A \"quoted\" summary\ntags: [a, b]\n---\nBody text\n",
+        )?;
+
+        let output_path = render_markdown_file(
+            &markdown_path,
+            temp_dir.path(),
+            &output_dir,
+            &MarkdownOptions::default(),
+            &Templates::default(),
+            "",
+            true,
+        )?;
+
+        let html = fs::read_to_string(output_path)?;
+        assert!(!html.contains("<hr"));
+        assert!(!html.contains("description:"));
+        assert!(html.contains("<p>Body text</p>"));
+        assert!(html.contains("<title>Meta title</title>"));
+        assert!(
+            html.contains("<meta name=\"description\" content=\"A &quot;quoted&quot; summary\">")
+        );
+        assert!(html.contains("<meta name=\"keywords\" content=\"a, b\">"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_front_matter_is_an_error() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let markdown_path = temp_dir.path().join("page.md");
+        fs::write(&markdown_path, "---\ndraft: maybe\n---\nBody\n")?;
+
+        let err = render_markdown_file(
+            &markdown_path,
+            temp_dir.path(),
+            temp_dir.path(),
+            &MarkdownOptions::default(),
+            &Templates::default(),
+            "",
+            true,
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("front matter"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rendered_page_title() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let markdown_path = temp_dir.path().join("page.md");
+        let output_dir = temp_dir.path().join("output");
+        fs::write(&markdown_path, "# Fish & Chips < Pie\n")?;
+
+        let output_path = render_markdown_file(
+            &markdown_path,
+            temp_dir.path(),
+            &output_dir,
+            &MarkdownOptions::default(),
+            &Templates::default(),
+            "",
+            true,
+        )?;
+
+        let html = fs::read_to_string(output_path)?;
+        assert!(html.contains("<title>Fish &amp; Chips &lt; Pie</title>"));
+        Ok(())
+    }
+
     #[test]
     fn test_code_block_rendering() {
         let options = MarkdownOptions::default();
@@ -253,4 +1084,132 @@ mod tests {
         assert!(html.contains("<pre><code"));
         assert!(html.contains("class=\"language-rust\""));
     }
+
+    #[test]
+    fn test_code_block_highlighting() {
+        let mut options = MarkdownOptions::default();
+        let markdown = "```rust {2}\nlet a = 1;\nlet b = \"<b>\";\n```\n\n    indented <code>\n";
+
+        let html = markdown_to_html(markdown, &options);
+        assert!(html.contains("<div class=\"highlight\">"));
+        assert!(html.contains("hl-keyword"));
+        assert!(html.contains("<span class=\"line highlighted\">"));
+        assert!(html.contains("&lt;b&gt;"));
+        // Indented blocks have no language and are left alone
+        assert!(html.contains("<pre><code>indented &lt;code&gt;"));
+
+        options.line_numbers = true;
+        let html = markdown_to_html(markdown, &options);
+        assert!(html.contains("<div class=\"highlight line-numbers\">"));
+
+        options.disable_highlighting = true;
+        let html = markdown_to_html(markdown, &options);
+        assert!(!html.contains("hl-"));
+        assert!(html.contains("<pre><code class=\"language-rust\">"));
+    }
+
+    #[test]
+    fn test_heading_anchors() {
+        let options = MarkdownOptions::default();
+        let markdown = "# Intro\n\n## Usage\n\n## Usage\n\n### Custom {#mine .wide}\n\n## [Link](other.md) & `code`\n";
+        let rendered = render_markdown(markdown, None, &options);
+
+        let ids: Vec<_> = rendered.headings.iter().map(|h| h.id.as_str()).collect();
+        assert_eq!(ids, ["intro", "usage", "usage-1", "mine", "link--code"]);
+        assert_eq!(rendered.headings[4].text, "Link & code");
+        assert_eq!(rendered.headings[3].level, 3);
+
+        let html = rendered.html;
+        assert!(html.contains("<h2 id=\"usage-1\">Usage<a class=\"anchor\" href=\"#usage-1\""));
+        assert!(html.contains("<h3 id=\"mine\" class=\"wide\">Custom"));
+        assert!(html.contains("<a href=\"other.html\">Link</a>"));
+    }
+
+    #[test]
+    fn test_table_of_contents() -> Result<()> {
+        let options = MarkdownOptions::default();
+        let html = markdown_to_html("# Title\n\n[[toc]]\n\n## First\n\n## Second\n", &options);
+        assert!(!html.contains("[[toc]]"));
+        let toc = html.find("<nav class=\"toc\">").unwrap();
+        assert!(toc < html.find("<h2").unwrap());
+        assert!(html.contains("<a href=\"#second\">Second</a>"));
+
+        // Front matter puts the table of contents in the template slot
+        let temp_dir = TempDir::new()?;
+        let markdown_path = temp_dir.path().join("page.md");
+        fs::write(&markdown_path, "---\ntoc: true\n---\n## First\n")?;
+        let output_path = render_markdown_file(
+            &markdown_path,
+            temp_dir.path(),
+            temp_dir.path(),
+            &options,
+            &Templates::default(),
+            "",
+            false,
+        )?;
+        let html = fs::read_to_string(output_path)?;
+        assert!(html.contains("<a href=\"#first\">First</a>"));
+
+        fs::write(&markdown_path, "## First\n")?;
+        let output_path = render_markdown_file(
+            &markdown_path,
+            temp_dir.path(),
+            temp_dir.path(),
+            &options,
+            &Templates::default(),
+            "",
+            false,
+        )?;
+        assert!(!fs::read_to_string(output_path)?.contains("class=\"toc\""));
+        Ok(())
+    }
+
+    #[test]
+    fn test_options_fingerprint() {
+        let options = MarkdownOptions::default();
+        assert_eq!(
+            options.fingerprint(),
+            MarkdownOptions::default().fingerprint()
+        );
+
+        // The default theme, named or not, renders the same
+        let named = MarkdownOptions {
+            highlight_theme_light: Some(highlight::DEFAULT_LIGHT_THEME.to_string()),
+            ..MarkdownOptions::default()
+        };
+        assert_eq!(named.fingerprint(), options.fingerprint());
+
+        let changed = [
+            MarkdownOptions {
+                line_numbers: true,
+                ..MarkdownOptions::default()
+            },
+            MarkdownOptions {
+                highlight_theme_dark: Some("Solarized (dark)".to_string()),
+                ..MarkdownOptions::default()
+            },
+            MarkdownOptions {
+                extensions: vec!["md".to_string()],
+                ..MarkdownOptions::default()
+            },
+        ];
+        for other in changed {
+            assert_ne!(other.fingerprint(), options.fingerprint());
+        }
+    }
+
+    #[test]
+    fn test_highlight_css() -> Result<()> {
+        let mut options = MarkdownOptions::default();
+        assert!(options
+            .highlight_css()?
+            .contains("prefers-color-scheme: dark"));
+
+        options.highlight_theme_light = Some("no-such-theme".to_string());
+        assert!(options.highlight_css().is_err());
+
+        options.disable_highlighting = true;
+        assert_eq!(options.highlight_css()?, "");
+        Ok(())
+    }
 }
@@ -0,0 +1,469 @@
+use crate::{
+    assets::is_asset,
+    filter::{ContentFilter, FilterOptions},
+    index_listing_html,
+    markdown::{layout_page, render_page_content, MarkdownOptions, PageContent},
+    nav::{is_directory_index, Nav},
+    site::{Page, Site},
+    templates::Templates,
+    INDEX_LISTING_FILE,
+};
+use anyhow::{Context, Result};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fs,
+    hash::{Hash, Hasher},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+/// A page rendered from the markdown whose contents hash to `hash`, laid
+/// out with the navigation whose fingerprint is `nav`
+#[derive(Debug, Clone)]
+struct CachedPage {
+    hash: u64,
+    nav: String,
+    content: PageContent,
+    html: String,
+}
+
+/// Pages rendered on request and kept in memory, for serving without an
+/// output directory.
+///
+/// A cached page is reused while its markdown and the site navigation are
+/// unchanged. The watcher invalidates pages when their sources change. When
+/// the set of pages changes, the navigation is rebuilt and pages are laid
+/// out again with it on their next request, reusing their rendered
+/// markdown. A template change discards the whole cache.
+///
+/// The store is shared between requests and the watcher. Its lock is only
+/// held to look pages up and to invalidate them, never while rendering, so
+/// pages render concurrently.
+#[derive(Debug)]
+pub struct PageStore {
+    content_dir: PathBuf,
+    options: MarkdownOptions,
+    filter: ContentFilter,
+
+    /// Stylesheet for highlighted code, the same on every page
+    styles: String,
+
+    state: Mutex<StoreState>,
+}
+
+/// The parts of a [`PageStore`] that change as the content does
+#[derive(Debug)]
+struct StoreState {
+    templates: Arc<Templates>,
+
+    /// Counts template changes, so pages rendered with replaced templates
+    /// aren't cached
+    generation: u64,
+
+    site: Site,
+
+    /// The site navigation, built on first use after the pages change
+    nav: Option<Arc<Nav>>,
+
+    cache: HashMap<PathBuf, CachedPage>,
+}
+
+/// What a request path refers to in the content directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PageSource {
+    /// A markdown page
+    Markdown(PathBuf),
+    /// The generated listing of every page
+    Listing,
+}
+
+impl PageStore {
+    /// Creates a store for the pages in the content directory that the filter includes
+    pub fn new(
+        content_dir: &Path,
+        options: MarkdownOptions,
+        filter: &FilterOptions,
+        templates: Templates,
+    ) -> Result<Self> {
+        // Watcher events use resolved paths, so pages are keyed the same way
+        let content_dir = content_dir
+            .canonicalize()
+            .with_context(|| format!("Content directory not found: {}", content_dir.display()))?;
+        let filter = ContentFilter::new(&content_dir, filter)?;
+        let site = Site::scan(&filter, &options).context("Failed to scan content directory")?;
+        for (_, e) in site.conflicts() {
+            eprintln!("Error rendering markdown: {}", e);
+        }
+        let styles = options.highlight_css()?;
+
+        Ok(Self {
+            content_dir,
+            options,
+            filter,
+            styles,
+            state: Mutex::new(StoreState {
+                templates: Arc::new(templates),
+                generation: 0,
+                site,
+                nav: None,
+                cache: HashMap::new(),
+            }),
+        })
+    }
+
+    /// Locks the changing state, recovering it if a request panicked while holding it
+    fn state(&self) -> MutexGuard<'_, StoreState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The resolved content directory
+    pub fn content_dir(&self) -> &Path {
+        &self.content_dir
+    }
+
+    /// The filter deciding which files in the content directory are served
+    pub fn filter(&self) -> &ContentFilter {
+        &self.filter
+    }
+
+    /// The options pages are rendered with
+    pub fn options(&self) -> &MarkdownOptions {
+        &self.options
+    }
+
+    /// The templates pages are rendered with
+    pub fn templates(&self) -> Arc<Templates> {
+        self.state().templates.clone()
+    }
+
+    /// Replaces the templates after they changed, making every page stale
+    pub fn set_templates(&self, templates: Templates) {
+        let mut state = self.state();
+        state.templates = Arc::new(templates);
+        state.generation += 1;
+        state.cache.clear();
+    }
+
+    /// Checks whether a relative path is an asset that is served as it is
+    pub fn is_asset(&self, rel_path: &Path) -> bool {
+        is_asset(rel_path, &self.options)
+            && self.filter.is_included(&self.content_dir.join(rel_path))
+    }
+
+    /// Finds what a relative HTML path like `guide/setup.html` is rendered from,
+    /// mirroring where pages are written in a build.
+    ///
+    /// Returns `None` for anything that isn't a page.
+    pub fn resolve(&self, rel_path: &Path) -> Option<PageSource> {
+        if rel_path == Path::new(INDEX_LISTING_FILE) {
+            return Some(PageSource::Listing);
+        }
+        if rel_path.extension().is_none_or(|ext| ext != "html") {
+            return None;
+        }
+
+        let dir = self.content_dir.join(rel_path.parent()?);
+        if rel_path.file_name()? == "index.html" {
+            // The directory's landing page, or the listing at the root
+            return self
+                .find_source(&dir, "index")
+                .or_else(|| self.find_source(&dir, "README"))
+                .map(PageSource::Markdown)
+                .or_else(|| (dir == self.content_dir).then_some(PageSource::Listing));
+        }
+
+        let stem = rel_path.file_stem()?.to_str()?;
+        self.find_source(&dir, stem)
+            .filter(|source| !is_directory_index(source, &self.options))
+            .map(PageSource::Markdown)
+    }
+
+    /// Checks whether a relative path requested without a trailing slash names
+    /// a directory with a landing page, which is served with the slash added
+    pub fn is_page_directory(&self, rel_path: &Path) -> bool {
+        self.content_dir.join(rel_path).is_dir()
+            && matches!(
+                self.resolve(&rel_path.join("index.html")),
+                Some(PageSource::Markdown(_))
+            )
+    }
+
+    /// Finds the markdown file named `stem` with any markdown extension in `dir`.
+    /// If there are several, the one whose extension comes first wins, as in a build.
+    fn find_source(&self, dir: &Path, stem: &str) -> Option<PathBuf> {
+        fs::read_dir(dir)
+            .ok()?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| {
+                path.file_stem().is_some_and(|name| name == stem)
+                    && self.options.is_markdown(path)
+                    && path.is_file()
+                    && self.filter.is_included(path)
+            })
+            .min_by_key(|source| (self.options.extension_rank(source), source.clone()))
+    }
+
+    /// Renders the page for a relative HTML path, reusing the cached page if
+    /// its markdown hasn't changed.
+    ///
+    /// Returns `None` if the path isn't a page.
+    pub fn render(&self, rel_path: &Path) -> Option<Result<String>> {
+        match self.resolve(rel_path)? {
+            PageSource::Listing => Some(self.render_listing()),
+            PageSource::Markdown(source) => Some(self.render_markdown(&source)),
+        }
+    }
+
+    fn render_listing(&self) -> Result<String> {
+        let (pages, templates) = {
+            let state = self.state();
+            let pages: Vec<Page> = state.site.pages().cloned().collect();
+            (pages, state.templates.clone())
+        };
+        index_listing_html(&pages, &self.content_dir, &self.options, &templates, true)
+    }
+
+    fn render_markdown(&self, source: &Path) -> Result<String> {
+        let markdown = fs::read(source)
+            .with_context(|| format!("Failed to read markdown file: {}", source.display()))?;
+        let mut hasher = DefaultHasher::new();
+        markdown.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let (cached, nav, templates, generation) = {
+            let mut state = self.state();
+            let cached = state
+                .cache
+                .get(source)
+                .filter(|cached| cached.hash == hash)
+                .cloned();
+
+            // Pages created since the last scan still need to appear in the navigation
+            if cached.is_none() && state.site.update(source) {
+                state.nav = None;
+            }
+            let state = &mut *state;
+            let nav = state
+                .nav
+                .get_or_insert_with(|| Arc::new(Nav::for_site(&state.site, &self.content_dir)))
+                .clone();
+            (cached, nav, state.templates.clone(), state.generation)
+        };
+
+        let content = match cached {
+            Some(cached) if cached.nav == nav.fingerprint() => return Ok(cached.html),
+            Some(cached) => cached.content,
+            None => render_page_content(source, &self.options)?,
+        };
+        let html = layout_page(
+            source,
+            &content,
+            &templates,
+            &nav.render(source),
+            &self.styles,
+            true,
+        )?;
+
+        // The page is still current if the templates weren't replaced while
+        // it rendered. A changed source or navigation shows in its hash or
+        // fingerprint on the next request.
+        let mut state = self.state();
+        if state.generation == generation {
+            state.cache.insert(
+                source.to_path_buf(),
+                CachedPage {
+                    hash,
+                    nav: nav.fingerprint().to_string(),
+                    content,
+                    html: html.clone(),
+                },
+            );
+        }
+        Ok(html)
+    }
+
+    /// Fails if another markdown file renders to the same page as `source`
+    pub fn check_output(&self, source: &Path) -> Result<()> {
+        self.state().site.check_output(source)
+    }
+
+    /// Every markdown file left out because another renders to the same page
+    pub fn conflicts(&self) -> Vec<(PathBuf, anyhow::Error)> {
+        self.state()
+            .site
+            .conflicts()
+            .map(|(source, e)| (source.to_path_buf(), e))
+            .collect()
+    }
+
+    /// Forgets the rendered page for a markdown file that changed or was removed.
+    ///
+    /// Returns true if the set of pages changed, which changes every page's
+    /// navigation.
+    pub fn invalidate(&self, source: &Path) -> bool {
+        let mut state = self.state();
+        let pages_changed = if source.is_file() {
+            state.site.update(source)
+        } else {
+            state.site.remove(source)
+        };
+
+        state.cache.remove(source);
+        if pages_changed {
+            state.nav = None;
+        }
+        pages_changed
+    }
+}
+
+/// Converts a decoded request path to a path relative to the content
+/// directory, with directory requests mapped to their `index.html`.
+///
+/// Returns `None` for paths that try to leave the content directory or
+/// reach hidden files.
+pub fn relative_request_path(request_path: &str) -> Option<PathBuf> {
+    let mut rel_path = PathBuf::new();
+    for segment in request_path.split('/').filter(|s| !s.is_empty()) {
+        let is_normal = matches!(
+            Path::new(segment).components().collect::<Vec<_>>()[..],
+            [Component::Normal(_)]
+        );
+        if !is_normal || segment.starts_with('.') || segment.contains('\\') {
+            return None;
+        }
+        rel_path.push(segment);
+    }
+
+    if request_path.is_empty() || request_path.ends_with('/') {
+        rel_path.push("index.html");
+    }
+    Some(rel_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_relative_request_path() {
+        let rel = |path: &str| relative_request_path(path);
+        assert_eq!(rel("/"), Some(PathBuf::from("index.html")));
+        assert_eq!(rel("/guide/"), Some(PathBuf::from("guide/index.html")));
+        assert_eq!(rel("/guide"), Some(PathBuf::from("guide")));
+        assert_eq!(rel("/a/b.html"), Some(PathBuf::from("a/b.html")));
+        assert_eq!(rel("/a//b.png"), Some(PathBuf::from("a/b.png")));
+        assert_eq!(rel("/../secret"), None);
+        assert_eq!(rel("/a/./b"), None);
+        assert_eq!(rel("/.git/config"), None);
+        assert_eq!(rel("/a\\..\\b"), None);
+    }
+
+    #[test]
+    fn test_resolve() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content_dir = temp_dir.path();
+        fs::create_dir_all(content_dir.join("guide"))?;
+        fs::create_dir_all(content_dir.join("empty"))?;
+        fs::write(content_dir.join("page.md"), "# Page")?;
+        fs::write(content_dir.join("guide").join("README.md"), "# Guide")?;
+        fs::write(content_dir.join("guide").join("setup.Markdown"), "# Setup")?;
+        fs::create_dir_all(content_dir.join("node_modules"))?;
+        fs::write(content_dir.join("node_modules").join("dep.md"), "# Dep")?;
+        fs::write(content_dir.join("node_modules").join("dep.js"), "")?;
+
+        let store = PageStore::new(
+            content_dir,
+            MarkdownOptions::default(),
+            &FilterOptions::default(),
+            Templates::default(),
+        )?;
+        let root = store.content_dir().to_path_buf();
+        let resolve = |path: &str| store.resolve(Path::new(path));
+
+        assert_eq!(
+            resolve("page.html"),
+            Some(PageSource::Markdown(root.join("page.md")))
+        );
+        assert_eq!(
+            resolve("guide/index.html"),
+            Some(PageSource::Markdown(root.join("guide").join("README.md")))
+        );
+        assert_eq!(
+            resolve("guide/setup.html"),
+            Some(PageSource::Markdown(
+                root.join("guide").join("setup.Markdown")
+            ))
+        );
+        assert_eq!(resolve("index.html"), Some(PageSource::Listing));
+        assert_eq!(resolve("_index.html"), Some(PageSource::Listing));
+        assert_eq!(resolve("guide/README.html"), None);
+        assert_eq!(resolve("empty/index.html"), None);
+        assert_eq!(resolve("missing.html"), None);
+        assert_eq!(resolve("page.md"), None);
+
+        // Directories are only redirected to when they have a landing page
+        assert!(store.is_page_directory(Path::new("guide")));
+        assert!(!store.is_page_directory(Path::new("empty")));
+        assert!(!store.is_page_directory(Path::new("page.md")));
+        assert!(!store.is_page_directory(Path::new("node_modules")));
+
+        // Excluded files are neither pages nor assets
+        assert_eq!(resolve("node_modules/dep.html"), None);
+        assert!(!store.is_asset(Path::new("node_modules/dep.js")));
+        assert!(store.is_asset(Path::new("guide/logo.png")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_caches_until_invalidated() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content_dir = temp_dir.path();
+        fs::write(content_dir.join("page.md"), "# First")?;
+
+        let store = PageStore::new(
+            content_dir,
+            MarkdownOptions::default(),
+            &FilterOptions::default(),
+            Templates::default(),
+        )?;
+        let page = Path::new("page.html");
+        let html = store.render(page).unwrap()?;
+        assert!(html.contains("First"));
+        assert!(html.contains("EventSource"));
+        assert_eq!(store.state().cache.len(), 1);
+
+        // Edits are picked up through the content hash
+        fs::write(content_dir.join("page.md"), "# Second")?;
+        assert!(store.render(page).unwrap()?.contains("Second"));
+
+        // A new page changes every cached page's navigation, which is laid
+        // out again around the cached content
+        let added = store.content_dir().join("added.md");
+        fs::write(&added, "# Added")?;
+        let source = store.content_dir().join("page.md");
+        let cached = store.state().cache[&source].clone();
+        assert!(store.invalidate(&added));
+        assert!(store.state().nav.is_none());
+        let html = store.render(page).unwrap()?;
+        assert!(html.contains("added.html"));
+        let relaid = store.state().cache[&source].clone();
+        assert_eq!(relaid.content, cached.content);
+        assert_ne!(relaid.nav, cached.nav);
+
+        // A template change discards the cache, and the next request renders
+        // the page with the new templates
+        store.set_templates(Templates::default());
+        assert!(store.state().cache.is_empty());
+        store.render(page).unwrap()?;
+        assert_eq!(store.state().cache.len(), 1);
+
+        // Nothing is written to disk
+        let mut entries: Vec<_> = fs::read_dir(content_dir)?
+            .map(|entry| entry.map(|e| e.file_name()))
+            .collect::<std::io::Result<_>>()?;
+        entries.sort();
+        assert_eq!(entries, ["added.md", "page.md"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,503 @@
+use crate::{
+    cache, format_display_name,
+    markdown::MarkdownOptions,
+    site::{Page, Site},
+};
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+/// The site navigation, built once from the site's pages and rendered for
+/// each page with that page marked as current
+#[derive(Debug, Clone, Default)]
+pub struct Nav {
+    content_dir: PathBuf,
+    root: NavDir,
+    fingerprint: String,
+}
+
+/// A directory in the navigation tree
+#[derive(Debug, Clone, Default)]
+struct NavDir {
+    /// Directory name, empty for the content directory itself
+    name: String,
+
+    /// The directory's landing page
+    index: Option<NavPage>,
+
+    items: Vec<NavItem>,
+}
+
+#[derive(Debug, Clone)]
+enum NavItem {
+    Page(NavPage),
+    Dir(NavDir),
+}
+
+/// A page as it appears in the navigation
+#[derive(Debug, Clone)]
+struct NavPage {
+    source: PathBuf,
+
+    /// Path of the page's HTML relative to the output directory
+    href: String,
+
+    title: String,
+    weight: Option<i64>,
+}
+
+impl Nav {
+    /// Builds the navigation for the pages of a site
+    pub fn for_site(site: &Site, content_dir: &Path) -> Self {
+        Self::new(site.pages(), content_dir, |source| {
+            site.is_directory_index(source)
+        })
+    }
+
+    /// Builds the navigation for `pages`, with `is_index` telling which of
+    /// them are the landing pages of their directories
+    pub fn new<'a>(
+        pages: impl IntoIterator<Item = &'a Page>,
+        content_dir: &Path,
+        is_index: impl Fn(&Path) -> bool,
+    ) -> Self {
+        let root = NavDir::build(pages, content_dir, is_index);
+        let mut listing = String::new();
+        write_items(&mut listing, &root, "", None);
+        Self {
+            content_dir: content_dir.to_path_buf(),
+            fingerprint: cache::fingerprint([listing.as_bytes()]),
+            root,
+        }
+    }
+
+    /// Identifies the pages, titles and links in the navigation, which
+    /// together with the current page decide how it renders
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    /// Renders the navigation sidebar for the page built from `current`.
+    ///
+    /// Links are relative to the page, the page itself is marked as current and
+    /// only the directories leading to it start expanded.
+    pub fn render(&self, current: &Path) -> String {
+        // Links from a nested page need to climb back to the output root
+        let depth = current
+            .strip_prefix(&self.content_dir)
+            .ok()
+            .and_then(Path::parent)
+            .map_or(0, |parent| parent.iter().count());
+        let base = "../".repeat(depth);
+
+        let mut html = String::from("<nav class=\"site-nav\">\n");
+        let _ = writeln!(
+            html,
+            "<a class=\"nav-home\" href=\"{}index.html\">{}</a>",
+            base,
+            crate::escape_html(
+                self.root
+                    .index
+                    .as_ref()
+                    .map_or("Index", |page| page.title.as_str())
+            )
+        );
+        html.push_str("<ul>\n");
+        write_items(&mut html, &self.root, &base, Some(current));
+        html.push_str("</ul>\n</nav>\n");
+        html
+    }
+}
+
+impl NavDir {
+    /// Builds the tree of published pages under the content directory
+    fn build<'a>(
+        pages: impl IntoIterator<Item = &'a Page>,
+        content_dir: &Path,
+        is_index: impl Fn(&Path) -> bool,
+    ) -> Self {
+        let mut root = Self::default();
+        for page in pages.into_iter().filter(|p| !p.meta.draft) {
+            let Ok(rel_path) = page.source.strip_prefix(content_dir) else {
+                continue;
+            };
+            let is_index = is_index(&page.source);
+            let Some(href) = relative_href(rel_path, is_index) else {
+                continue;
+            };
+            let nav_page = NavPage {
+                source: page.source.clone(),
+                href,
+                title: page.title.clone(),
+                weight: page.meta.weight,
+            };
+
+            // Walk down to the page's directory, creating nodes on the way
+            let mut dir = &mut root;
+            if let Some(parent) = rel_path.parent() {
+                for component in parent.iter() {
+                    dir = dir.subdir(&component.to_string_lossy());
+                }
+            }
+
+            if is_index && dir.index.is_none() {
+                dir.index = Some(nav_page);
+            } else {
+                dir.items.push(NavItem::Page(nav_page));
+            }
+        }
+        root.sort();
+        root
+    }
+
+    fn subdir(&mut self, name: &str) -> &mut Self {
+        let position = self
+            .items
+            .iter()
+            .position(|item| matches!(item, NavItem::Dir(dir) if dir.name == name));
+        let index = position.unwrap_or_else(|| {
+            self.items.push(NavItem::Dir(NavDir {
+                name: name.to_string(),
+                ..Default::default()
+            }));
+            self.items.len() - 1
+        });
+        match &mut self.items[index] {
+            NavItem::Dir(dir) => dir,
+            NavItem::Page(_) => unreachable!("position matched a directory"),
+        }
+    }
+
+    fn sort(&mut self) {
+        for item in &mut self.items {
+            if let NavItem::Dir(dir) = item {
+                dir.sort();
+            }
+        }
+        self.items.sort_by_key(NavItem::sort_key);
+    }
+
+    fn title(&self) -> String {
+        match &self.index {
+            Some(page) => page.title.clone(),
+            None => format_display_name(split_order_prefix(&self.name).1),
+        }
+    }
+
+    fn contains(&self, source: &Path) -> bool {
+        self.index
+            .as_ref()
+            .is_some_and(|page| page.source == source)
+            || self.items.iter().any(|item| match item {
+                NavItem::Page(page) => page.source == source,
+                NavItem::Dir(dir) => dir.contains(source),
+            })
+    }
+}
+
+impl NavItem {
+    /// Orders by front matter weight, then numeric file name prefix, then name
+    fn sort_key(&self) -> (bool, Option<i64>, bool, Option<u64>, String) {
+        let (weight, name) = match self {
+            NavItem::Page(page) => (
+                page.weight,
+                page.source
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+            ),
+            NavItem::Dir(dir) => (
+                dir.index.as_ref().and_then(|page| page.weight),
+                dir.name.clone(),
+            ),
+        };
+        let prefix = split_order_prefix(&name).0;
+        (weight.is_none(), weight, prefix.is_none(), prefix, name)
+    }
+}
+
+/// Splits a numeric ordering prefix like `01-` from a file or directory name.
+///
+/// Returns the prefix value, if any, and the rest of the name.
+pub fn split_order_prefix(name: &str) -> (Option<u64>, &str) {
+    let digits = name.len() - name.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let rest = &name[digits..];
+    match rest.strip_prefix(['-', '_', '.', ' ']) {
+        Some(rest) if digits > 0 && !rest.is_empty() => (name[..digits].parse().ok(), rest),
+        _ => (None, name),
+    }
+}
+
+/// Checks whether a page is the landing page of its directory, rendered as
+/// the directory's `index.html`.
+///
+/// `index.md` takes precedence, so a `README.md` next to one is an ordinary
+/// page. Any markdown extension counts for either.
+pub fn is_directory_index(source: &Path, options: &MarkdownOptions) -> bool {
+    if !options.is_markdown(source) {
+        return false;
+    }
+    match source.file_stem().and_then(|stem| stem.to_str()) {
+        Some("index") => true,
+        Some("README") => !options.extensions.iter().any(|ext| {
+            [ext.to_lowercase(), ext.to_uppercase()]
+                .iter()
+                .any(|ext| source.with_file_name(format!("index.{}", ext)).exists())
+        }),
+        _ => false,
+    }
+}
+
+/// Name of the HTML file a page is rendered to in its directory.
+///
+/// A directory's landing page becomes its `index.html`. Otherwise the whole
+/// stem is kept, so `release-1.2.md` becomes `release-1.2.html`.
+pub fn output_file_name(source: &Path, options: &MarkdownOptions) -> Option<String> {
+    html_file_name(source, is_directory_index(source, options))
+}
+
+/// Name of the HTML file for `source`, given whether it is the landing
+/// page of its directory
+pub fn html_file_name(source: &Path, is_index: bool) -> Option<String> {
+    if is_index {
+        return Some("index.html".to_string());
+    }
+    let stem = source.file_stem()?.to_string_lossy();
+    Some(format!("{}.html", stem))
+}
+
+/// Path of a page's HTML relative to the output directory, using `/` separators
+pub fn page_href(source: &Path, content_dir: &Path, options: &MarkdownOptions) -> Option<String> {
+    let rel_path = source.strip_prefix(content_dir).ok()?;
+    relative_href(rel_path, is_directory_index(source, options))
+}
+
+/// Path of the HTML for the page at `rel_path` in the content directory,
+/// relative to the output directory
+fn relative_href(rel_path: &Path, is_index: bool) -> Option<String> {
+    let file_name = html_file_name(rel_path, is_index)?;
+
+    let mut href = String::new();
+    if let Some(parent) = rel_path.parent() {
+        for component in parent.iter() {
+            href.push_str(&component.to_string_lossy());
+            href.push('/');
+        }
+    }
+    href.push_str(&file_name);
+    Some(href)
+}
+
+/// Renders the whole tree, expanded, as list items for the index page
+pub fn render_index_list<'a>(
+    pages: impl IntoIterator<Item = &'a Page>,
+    content_dir: &Path,
+    options: &MarkdownOptions,
+) -> String {
+    let root = NavDir::build(pages, content_dir, |source| {
+        is_directory_index(source, options)
+    });
+    let mut html = String::new();
+    write_items(&mut html, &root, "", None);
+    html
+}
+
+/// Writes `<li>` elements for the contents of a directory.
+///
+/// Directories are collapsible; they start open on the index, or on a page
+/// when they contain that page.
+fn write_items(html: &mut String, dir: &NavDir, base: &str, current: Option<&Path>) {
+    let link = |html: &mut String, page: &NavPage, text: &str| {
+        let current_attr = if current == Some(page.source.as_path()) {
+            " class=\"current\" aria-current=\"page\""
+        } else {
+            ""
+        };
+        let _ = write!(
+            html,
+            "<a href=\"{}{}\"{}>{}</a>",
+            base,
+            crate::escape_html(&page.href),
+            current_attr,
+            crate::escape_html(text)
+        );
+    };
+
+    for item in &dir.items {
+        match item {
+            NavItem::Page(page) => {
+                html.push_str("<li>");
+                link(html, page, &page.title);
+                html.push_str("</li>\n");
+            }
+            NavItem::Dir(subdir) => {
+                let open = current.is_none_or(|current| subdir.contains(current));
+                let _ = write!(
+                    html,
+                    "<li class=\"nav-dir\"><details{}><summary>",
+                    if open { " open" } else { "" }
+                );
+                match &subdir.index {
+                    Some(page) => link(html, page, &subdir.title()),
+                    None => html.push_str(&crate::escape_html(&subdir.title())),
+                }
+                html.push_str("</summary>\n<ul>\n");
+                write_items(html, subdir, base, current);
+                html.push_str("</ul>\n</details></li>\n");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::front_matter::PageMeta;
+    use std::path::PathBuf;
+
+    fn page(source: &str, title: &str, weight: Option<i64>) -> Page {
+        Page {
+            source: PathBuf::from("/docs").join(source),
+            title: title.to_string(),
+            meta: PageMeta {
+                weight,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_split_order_prefix() {
+        assert_eq!(split_order_prefix("01-intro.md"), (Some(1), "intro.md"));
+        assert_eq!(split_order_prefix("2_setup"), (Some(2), "setup"));
+        assert_eq!(split_order_prefix("2024"), (None, "2024"));
+        assert_eq!(split_order_prefix("10-"), (None, "10-"));
+        assert_eq!(split_order_prefix("guide"), (None, "guide"));
+    }
+
+    #[test]
+    fn test_page_href() {
+        let options = MarkdownOptions::default();
+        let href = |source: &str| {
+            page_href(
+                &Path::new("/docs").join(source),
+                Path::new("/docs"),
+                &options,
+            )
+        };
+        assert_eq!(href("intro.md").as_deref(), Some("intro.html"));
+        assert_eq!(
+            href("guide/advanced/setup.md").as_deref(),
+            Some("guide/advanced/setup.html")
+        );
+        assert_eq!(href("guide/README.md").as_deref(), Some("guide/index.html"));
+        assert_eq!(href("index.md").as_deref(), Some("index.html"));
+        assert_eq!(href("notes.mdown").as_deref(), Some("notes.html"));
+        assert_eq!(
+            href("guide/README.markdown").as_deref(),
+            Some("guide/index.html")
+        );
+        assert_eq!(
+            page_href(Path::new("/elsewhere/a.md"), Path::new("/docs"), &options),
+            None
+        );
+    }
+
+    #[test]
+    fn test_index_md_takes_precedence_over_readme() -> std::io::Result<()> {
+        let options = MarkdownOptions::default();
+        let temp_dir = tempfile::TempDir::new()?;
+        let readme = temp_dir.path().join("README.md");
+        let index = temp_dir.path().join("index.markdown");
+        std::fs::write(&readme, "# Readme")?;
+        assert!(is_directory_index(&readme, &options));
+
+        std::fs::write(&index, "# Index")?;
+        assert!(is_directory_index(&index, &options));
+        assert!(!is_directory_index(&readme, &options));
+        assert!(!is_directory_index(Path::new("guide/setup.md"), &options));
+        assert!(!is_directory_index(Path::new("guide/index.html"), &options));
+        Ok(())
+    }
+
+    #[test]
+    fn test_nav_tree_ordering() {
+        let pages = [
+            page("zebra.md", "Zebra", None),
+            page("10-last.md", "Last", None),
+            page("2-second.md", "Second", None),
+            page("heavy.md", "Heavy", Some(1)),
+            page("02-guide/README.md", "The Guide", None),
+            page("02-guide/setup.md", "Setup", None),
+            page("01-basics/intro.md", "Intro", None),
+        ];
+        let html = render_index_list(&pages, Path::new("/docs"), &MarkdownOptions::default());
+
+        let order: Vec<usize> = [
+            "Heavy",
+            "Basics",
+            "The Guide",
+            "Setup",
+            "Second",
+            "Last",
+            "Zebra",
+        ]
+        .iter()
+        .map(|title| html.find(&format!(">{}<", title)).unwrap())
+        .collect();
+        assert!(order.windows(2).all(|w| w[0] < w[1]), "{}", html);
+
+        assert!(html.contains("<a href=\"02-guide/index.html\">The Guide</a>"));
+        assert!(html.contains("<a href=\"02-guide/setup.html\">Setup</a>"));
+        assert_eq!(html.matches("<details open>").count(), 2);
+    }
+
+    #[test]
+    fn test_nav_marks_current_page() {
+        let mut draft = page("draft.md", "Draft", None);
+        draft.meta.draft = true;
+        let pages = [
+            page("README.md", "Home", None),
+            page("top.md", "Top", None),
+            page("a/one.md", "One", None),
+            page("b/two.md", "Two", None),
+            draft,
+        ];
+        let options = MarkdownOptions::default();
+        let nav = Nav::new(&pages, Path::new("/docs"), |source| {
+            is_directory_index(source, &options)
+        });
+        let html = nav.render(Path::new("/docs/a/one.md"));
+
+        assert!(html.contains("<a class=\"nav-home\" href=\"../index.html\">Home</a>"));
+        assert!(html.contains("<a href=\"../top.html\">Top</a>"));
+        assert!(html
+            .contains("<a href=\"../a/one.html\" class=\"current\" aria-current=\"page\">One</a>"));
+        assert!(!html.contains("Draft"));
+
+        // Only the directory holding the current page is expanded
+        assert_eq!(html.matches("<details open>").count(), 1);
+        assert_eq!(html.matches("<details>").count(), 1);
+
+        // The same tree renders for every page, marking each as current
+        let html = nav.render(Path::new("/docs/top.md"));
+        assert!(html.contains("<a class=\"nav-home\" href=\"index.html\">Home</a>"));
+        assert!(
+            html.contains("<a href=\"top.html\" class=\"current\" aria-current=\"page\">Top</a>")
+        );
+        assert_eq!(html.matches("<details>").count(), 2);
+
+        // The fingerprint follows the pages, not the current one
+        let retitled = [page("README.md", "Welcome", None)];
+        let other = Nav::new(&retitled, Path::new("/docs"), |_| true);
+        assert_ne!(nav.fingerprint(), other.fingerprint());
+        assert_eq!(
+            nav.fingerprint(),
+            Nav::new(&pages, Path::new("/docs"), |source| {
+                is_directory_index(source, &options)
+            })
+            .fingerprint()
+        );
+    }
+}
@@ -1,44 +1,103 @@
-use crate::{config::Config, render_all_markdown_files, watcher::setup_file_watcher};
+use crate::{
+    assets::copy_all_assets,
+    config::Config,
+    live::{ClientMessage, LiveEvent, LiveMessage, Viewer},
+    memory::{relative_request_path, PageStore},
+    render_all_markdown_files,
+    watcher::{setup_file_watcher, setup_memory_watcher},
+    INDEX_LISTING_FILE,
+};
 use anyhow::Result;
 use axum::{
-    extract::State,
-    response::sse::{Event, Sse},
+    body::Body,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, Request, State,
+    },
+    http::StatusCode,
+    response::{
+        sse::{Event, Sse},
+        Html, IntoResponse, Redirect, Response,
+    },
     routing::get,
     Router,
 };
 use futures::stream::Stream;
-use std::{path::PathBuf, sync::Arc};
-use tokio::sync::broadcast;
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
 use tower_http::services::ServeDir;
 
 /// Server state containing the broadcast channel for file changes
 #[derive(Clone)]
 pub struct ServerState {
-    tx: Arc<broadcast::Sender<PathBuf>>,
+    tx: Arc<broadcast::Sender<LiveEvent>>,
 }
 
-/// Start the live-md server with the given configuration
+/// Start the live-md server with the given configuration.
+///
+/// Pages are written to the output directory and served from there, or
+/// with `in_memory` rendered on request without writing any files.
 pub async fn start_server(config: Config) -> Result<()> {
-    // Create output directory if it doesn't exist
-    std::fs::create_dir_all(&config.output_dir)?;
-
-    // Initial render of all markdown files
-    render_all_markdown_files(&config.content_dir, &config.output_dir)?;
-
     // Set up broadcast channel for file changes
-    let (tx, _) = broadcast::channel::<PathBuf>(config.broadcast_capacity);
+    let (tx, _) = broadcast::channel::<LiveEvent>(config.broadcast_capacity);
     let tx = Arc::new(tx);
 
-    // Set up file watcher
-    let watcher_tx = tx.clone();
-    let watcher_output_dir = config.output_dir.clone();
-    setup_file_watcher(config.content_dir.clone(), watcher_output_dir, watcher_tx)?;
-
-    // Build router with static file serving and SSE endpoint
     let app = Router::new()
         .route("/events", get(sse_handler))
-        .nest_service("/", ServeDir::new(&config.output_dir))
-        .with_state(ServerState { tx });
+        .route("/ws", get(ws_handler))
+        .route(
+            "/_index",
+            get(|| async { Redirect::to(&format!("/{}", INDEX_LISTING_FILE)) }),
+        );
+    let templates = config.templates()?;
+
+    let app = if config.in_memory {
+        let store = Arc::new(PageStore::new(
+            &config.content_dir,
+            config.markdown.clone(),
+            &config.filter,
+            templates,
+        )?);
+        setup_memory_watcher(store.clone(), config.debounce(), tx.clone())?;
+
+        app.fallback(move |request: Request| page_response(store.clone(), request))
+    } else {
+        // Create output directory if it doesn't exist
+        std::fs::create_dir_all(&config.output_dir)?;
+
+        // Initial render of all markdown files
+        let stats = render_all_markdown_files(
+            &config.content_dir,
+            &config.output_dir,
+            &config.markdown,
+            &config.filter,
+            &templates,
+            true,
+        )?;
+        println!("{}", stats);
+        copy_all_assets(
+            &config.content_dir,
+            &config.output_dir,
+            &config.markdown,
+            &config.filter,
+        )?;
+
+        // Set up file watcher
+        setup_file_watcher(
+            config.content_dir.clone(),
+            config.output_dir.clone(),
+            config.markdown.clone(),
+            config.filter.clone(),
+            templates,
+            config.debounce(),
+            tx.clone(),
+        )?;
+
+        app.nest_service("/", ServeDir::new(&config.output_dir))
+    };
+    let app = app.with_state(ServerState { tx });
 
     // Create server address
     let addr = config.socket_addr();
@@ -58,27 +117,157 @@ pub async fn start_server(config: Config) -> Result<()> {
     Ok(())
 }
 
-/// SSE handler for live reload functionality
+/// Serves a page rendered in memory, or an asset straight from the content directory
+async fn page_response(store: Arc<PageStore>, request: Request) -> Response {
+    let path = percent_decode_str(request.uri().path()).decode_utf8_lossy();
+    let Some(rel_path) = relative_request_path(&path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    // Rendering reads files, so it stays off the async workers
+    let rendered = tokio::task::spawn_blocking({
+        let store = store.clone();
+        move || {
+            if store.is_page_directory(&rel_path) {
+                return (None, false, true);
+            }
+            (store.render(&rel_path), store.is_asset(&rel_path), false)
+        }
+    })
+    .await;
+    let (page, is_asset, is_directory) = match rendered {
+        Ok(rendered) => rendered,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    // Like `ServeDir`, send directory requests to the path with a trailing slash
+    if is_directory {
+        let uri = request.uri();
+        let location = match uri.query() {
+            Some(query) => format!("{}/?{}", uri.path(), query),
+            None => format!("{}/", uri.path()),
+        };
+        return Redirect::temporary(&location).into_response();
+    }
+    match page {
+        Some(Ok(html)) => Html(html).into_response(),
+        Some(Err(e)) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)).into_response(),
+        None if is_asset => match ServeDir::new(store.content_dir()).try_call(request).await {
+            Ok(response) => response.map(Body::new),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        },
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Query parameters of the live reload event stream
+#[derive(Debug, Deserialize)]
+struct EventsQuery {
+    /// Page the browser is showing, like `guide/setup.html`. Without it
+    /// every change is sent.
+    page: Option<String>,
+}
+
+/// SSE handler for live reload functionality, sending each change that
+/// affects the subscribed page as a JSON message
 async fn sse_handler(
     State(state): State<ServerState>,
+    Query(query): Query<EventsQuery>,
 ) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
     let mut rx = state.tx.subscribe();
 
     let stream = async_stream::stream! {
-        loop {
-            match rx.recv().await {
-                Ok(_) => {
-                    yield Ok(Event::default().data("reload"));
+        let viewer = query.page.as_deref().map_or(Viewer::Any, Viewer::Page);
+        while let Some(message) = next_message(&mut rx, viewer).await {
+            yield Event::default().json_data(message);
+        }
+    };
+
+    Sse::new(stream)
+}
+
+/// Waits for the next message for `viewer`, leaving out changes to other
+/// pages. Returns `None` once the channel is closed.
+///
+/// A browser that fell behind has missed changes, so it reloads to catch up
+/// rather than being disconnected.
+async fn next_message(
+    rx: &mut broadcast::Receiver<LiveEvent>,
+    viewer: Viewer<'_>,
+) -> Option<LiveMessage> {
+    loop {
+        match rx.recv().await {
+            Ok(event) => {
+                if let Some(message) = event.message_for(viewer) {
+                    return Some(message);
                 }
-                Err(e) => {
-                    eprintln!("SSE error: {}", e);
+            }
+            Err(RecvError::Lagged(_)) => {
+                return Some(LiveMessage::Reload {
+                    url: None,
+                    scroll: None,
+                })
+            }
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// WebSocket handler for live reload, carrying the same messages as the
+/// event stream while the browser reports which page it is showing
+async fn ws_handler(ws: WebSocketUpgrade, State(state): State<ServerState>) -> Response {
+    let rx = state.tx.subscribe();
+    ws.on_upgrade(move |socket| live_socket(socket, rx))
+}
+
+/// What a WebSocket client last reported about itself
+#[derive(Debug, Default)]
+struct ClientState {
+    page: Option<String>,
+    scroll: Option<u64>,
+}
+
+/// Sends changes that affect the client's page until either side closes the
+/// socket. Until the client reports its page, it only hears about changes
+/// affecting every page.
+async fn live_socket(mut socket: WebSocket, mut rx: broadcast::Receiver<LiveEvent>) {
+    let mut client = ClientState::default();
+    loop {
+        let page = client.page.clone();
+        tokio::select! {
+            message = next_message(&mut rx, page.as_deref().map_or(Viewer::Unknown, Viewer::Page)) => {
+                let Some(mut message) = message else {
+                    break;
+                };
+                // Bring the page back to where it was after reloading
+                if let LiveMessage::Reload { scroll, .. } = &mut message {
+                    *scroll = client.scroll;
+                }
+                let text = match serde_json::to_string(&message) {
+                    Ok(text) => text,
+                    Err(e) => {
+                        eprintln!("WebSocket error: {}", e);
+                        continue;
+                    }
+                };
+                if socket.send(Message::Text(text)).await.is_err() {
                     break;
                 }
             }
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(ClientMessage::Visit { page, scroll }) => {
+                        client.page = Some(page);
+                        client.scroll = Some(scroll);
+                    }
+                    Err(e) => eprintln!("Invalid WebSocket message: {}", e),
+                },
+                // Pings are answered by axum
+                Some(Ok(Message::Binary(_) | Message::Ping(_) | Message::Pong(_))) => {}
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+            },
         }
-    };
-
-    Sse::new(stream)
+    }
 }
 
 #[cfg(test)]
@@ -127,4 +316,26 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_next_message_reloads_after_falling_behind() {
+        let (tx, mut rx) = broadcast::channel(2);
+        for _ in 0..3 {
+            tx.send(LiveEvent::Building).unwrap();
+        }
+        drop(tx);
+
+        let reload = LiveMessage::Reload {
+            url: None,
+            scroll: None,
+        };
+        let building = LiveMessage::BuildStatus(crate::live::BuildStatus::Building);
+        assert_eq!(next_message(&mut rx, Viewer::Any).await, Some(reload));
+        assert_eq!(
+            next_message(&mut rx, Viewer::Any).await,
+            Some(building.clone())
+        );
+        assert_eq!(next_message(&mut rx, Viewer::Any).await, Some(building));
+        assert_eq!(next_message(&mut rx, Viewer::Any).await, None);
+    }
 }
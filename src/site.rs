@@ -0,0 +1,317 @@
+use crate::{
+    collect_markdown_files,
+    filter::ContentFilter,
+    front_matter::{parse_front_matter, split_front_matter, PageMeta},
+    markdown::{fallback_title, page_title, MarkdownOptions},
+    nav::html_file_name,
+};
+use anyhow::{anyhow, Result};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A markdown page in the content directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    /// Path to the markdown source
+    pub source: PathBuf,
+
+    /// Title shown in the index and navigation
+    pub title: String,
+
+    /// Metadata from the page's front matter
+    pub meta: PageMeta,
+}
+
+impl Page {
+    /// Reads a markdown file to create its page.
+    ///
+    /// Unreadable files and invalid front matter still get a page, titled
+    /// after the first heading or file name. Rendering reports the error.
+    pub fn load(source: &Path) -> Self {
+        let Ok(markdown) = fs::read_to_string(source) else {
+            return Self {
+                source: source.to_path_buf(),
+                title: fallback_title(source),
+                meta: PageMeta::default(),
+            };
+        };
+
+        let (meta, body) = parse_front_matter(&markdown)
+            .unwrap_or_else(|_| (PageMeta::default(), split_front_matter(&markdown).1));
+
+        Self {
+            source: source.to_path_buf(),
+            title: page_title(&meta, body, source),
+            meta,
+        }
+    }
+}
+
+/// In-memory model of the pages in the content directory.
+///
+/// The watcher keeps this up to date so the index and navigation can be
+/// regenerated without rescanning the content directory.
+///
+/// When several sources in a directory render to the same HTML file, like
+/// `notes.md` and `notes.markdown`, the one whose extension comes first in
+/// the options is used. The others are left out of the site and reported
+/// as conflicts rather than overwriting it.
+#[derive(Debug, Clone, Default)]
+pub struct Site {
+    options: MarkdownOptions,
+    pages: BTreeMap<PathBuf, Page>,
+
+    /// Sources left out because another renders to the same file, mapped
+    /// to that source
+    shadowed: BTreeMap<PathBuf, PathBuf>,
+
+    /// Directories with an `index` page, whose README is an ordinary page
+    index_dirs: BTreeSet<PathBuf>,
+}
+
+impl Site {
+    /// Creates an empty model for pages rendered with the given options
+    pub fn new(options: &MarkdownOptions) -> Self {
+        Self {
+            options: options.clone(),
+            ..Self::default()
+        }
+    }
+
+    /// The options pages are rendered with
+    pub fn options(&self) -> &MarkdownOptions {
+        &self.options
+    }
+
+    /// Builds the model from the markdown pages the filter includes
+    pub fn scan(filter: &ContentFilter, options: &MarkdownOptions) -> Result<Self> {
+        let markdown_files = collect_markdown_files(filter, options)?;
+
+        let mut site = Self::new(options);
+        for path in markdown_files {
+            let page = Page::load(&path);
+            site.pages.insert(path, page);
+        }
+        site.find_shadowed();
+        Ok(site)
+    }
+
+    /// Adds or refreshes a page from its source on disk.
+    ///
+    /// Returns true if the set of pages, their titles or metadata changed.
+    pub fn update(&mut self, source: &Path) -> bool {
+        let page = Page::load(source);
+        match self.pages.insert(source.to_path_buf(), page.clone()) {
+            Some(existing) => existing != page,
+            None => {
+                self.find_shadowed();
+                true
+            }
+        }
+    }
+
+    /// Drops a page whose source no longer exists.
+    ///
+    /// Returns true if the page was known.
+    pub fn remove(&mut self, source: &Path) -> bool {
+        let removed = self.pages.remove(source).is_some();
+        if removed {
+            self.find_shadowed();
+        }
+        removed
+    }
+
+    /// Iterates over the pages in source path order, leaving out conflicting ones
+    pub fn pages(&self) -> impl Iterator<Item = &Page> {
+        self.pages
+            .values()
+            .filter(|page| !self.shadowed.contains_key(&page.source))
+    }
+
+    /// Paths of every markdown source in the site, leaving out conflicting ones
+    pub fn markdown_files(&self) -> Vec<PathBuf> {
+        self.pages().map(|page| page.source.clone()).collect()
+    }
+
+    /// Fails if `source` is left out of the site because another source in
+    /// its directory renders to the same HTML file
+    pub fn check_output(&self, source: &Path) -> Result<()> {
+        match self.shadowed.get(source) {
+            Some(used) => Err(anyhow!(
+                "{} renders to the same page as {}, which is used instead; rename or remove one of them",
+                source.display(),
+                used.display()
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks whether `source` is the landing page of its directory, like
+    /// [`crate::nav::is_directory_index`] but going by the pages in the site
+    /// rather than the file system
+    pub fn is_directory_index(&self, source: &Path) -> bool {
+        if !self.options.is_markdown(source) {
+            return false;
+        }
+        match source.file_stem().and_then(|stem| stem.to_str()) {
+            Some("index") => true,
+            Some("README") => source
+                .parent()
+                .is_none_or(|dir| !self.index_dirs.contains(dir)),
+            _ => false,
+        }
+    }
+
+    /// Every source left out because of a conflicting one, with its error
+    pub fn conflicts(&self) -> impl Iterator<Item = (&Path, anyhow::Error)> + '_ {
+        self.shadowed.keys().filter_map(|source| {
+            let error = self.check_output(source).err()?;
+            Some((source.as_path(), error))
+        })
+    }
+
+    /// Works out which sources are directory landing pages and which render
+    /// to the same file as another, keeping the one whose extension comes
+    /// first, then the first by name
+    fn find_shadowed(&mut self) {
+        self.index_dirs = self
+            .pages
+            .keys()
+            .filter(|source| source.file_stem().is_some_and(|stem| stem == "index"))
+            .filter_map(|source| Some(source.parent()?.to_path_buf()))
+            .collect();
+
+        let mut outputs: BTreeMap<PathBuf, Vec<&Path>> = BTreeMap::new();
+        for source in self.pages.keys() {
+            if let Some(file_name) = html_file_name(source, self.is_directory_index(source)) {
+                let output = source.with_file_name(file_name);
+                outputs.entry(output).or_default().push(source);
+            }
+        }
+
+        self.shadowed.clear();
+        for mut sources in outputs.into_values().filter(|sources| sources.len() > 1) {
+            sources.sort_by_key(|source| (self.options.extension_rank(source), *source));
+            for source in &sources[1..] {
+                self.shadowed
+                    .insert(source.to_path_buf(), sources[0].to_path_buf());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterOptions;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_scan() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content_dir = temp_dir.path();
+        fs::create_dir(content_dir.join("guide"))?;
+        fs::write(content_dir.join("first_page.md"), "No heading here")?;
+        fs::write(content_dir.join("guide").join("setup.md"), "# Setting up")?;
+        fs::write(content_dir.join("logo.png"), "png")?;
+
+        let filter = ContentFilter::new(content_dir, &FilterOptions::default())?;
+        let site = Site::scan(&filter, &MarkdownOptions::default())?;
+        let titles: Vec<_> = site.pages().map(|p| p.title.as_str()).collect();
+
+        assert_eq!(titles, ["First page", "Setting up"]);
+        assert_eq!(
+            site.markdown_files(),
+            [
+                content_dir.join("first_page.md"),
+                content_dir.join("guide").join("setup.md")
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_and_remove_report_changes() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut site = Site::default();
+        let page = temp_dir.path().join("page.md");
+        fs::write(&page, "# Original")?;
+
+        assert!(site.update(&page));
+        assert!(!site.update(&page));
+
+        // Editing the body keeps the title
+        fs::write(&page, "# Original\n\nMore text")?;
+        assert!(!site.update(&page));
+
+        // Retitling is a change
+        fs::write(&page, "# Retitled")?;
+        assert!(site.update(&page));
+        assert_eq!(site.pages().next().unwrap().title, "Retitled");
+
+        assert!(site.remove(&page));
+        assert!(!site.remove(&page));
+        assert_eq!(site.pages().count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_conflicting_outputs() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content_dir = temp_dir.path();
+        for name in [
+            "notes.markdown",
+            "notes.md",
+            "index.mdx",
+            "index.md",
+            "README.md",
+        ] {
+            fs::write(content_dir.join(name), "# Page")?;
+        }
+
+        let filter = ContentFilter::new(content_dir, &FilterOptions::default())?;
+        let mut site = Site::scan(&filter, &MarkdownOptions::default())?;
+
+        // The first configured extension wins, whatever the file names
+        assert_eq!(
+            site.markdown_files(),
+            [
+                content_dir.join("README.md"),
+                content_dir.join("index.md"),
+                content_dir.join("notes.md")
+            ]
+        );
+        let conflicts: Vec<_> = site.conflicts().map(|(path, _)| path).collect();
+        assert_eq!(
+            conflicts,
+            [
+                content_dir.join("index.mdx"),
+                content_dir.join("notes.markdown")
+            ]
+        );
+        assert!(site.check_output(&content_dir.join("notes.md")).is_ok());
+        let err = site.check_output(&content_dir.join("notes.markdown"));
+        assert!(err
+            .unwrap_err()
+            .to_string()
+            .contains("notes.md, which is used instead"));
+
+        // With an index page, the README beside it is an ordinary page
+        assert!(site.is_directory_index(&content_dir.join("index.md")));
+        assert!(!site.is_directory_index(&content_dir.join("README.md")));
+        assert!(!site.is_directory_index(&content_dir.join("notes.md")));
+
+        // Removing the page in use brings back the other one
+        fs::remove_file(content_dir.join("notes.md"))?;
+        assert!(site.remove(&content_dir.join("notes.md")));
+        assert!(site
+            .check_output(&content_dir.join("notes.markdown"))
+            .is_ok());
+        assert_eq!(site.conflicts().count(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,419 @@
+use crate::{cache::fingerprint, filter::is_hidden, front_matter::PageMeta};
+use anyhow::{anyhow, Context, Result};
+use minijinja::{context, value::Serde, Environment, Value};
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Template pages are rendered with, unless their front matter names another
+pub const PAGE_TEMPLATE: &str = "page.html";
+
+/// Template the page listing is rendered with
+pub const INDEX_TEMPLATE: &str = "index.html";
+
+/// Extensions of the files in the templates directory that are loaded as
+/// templates; anything else, such as images or editor backups, is skipped
+const TEMPLATE_EXTENSIONS: [&str; 3] = ["html", "jinja", "j2"];
+
+/// Templates that ship with live-md. A file with the same name in the
+/// templates directory replaces one of these.
+const BUILTIN_TEMPLATES: [(&str, &str); 5] = [
+    ("base.html", include_str!("templates/base.html")),
+    (PAGE_TEMPLATE, include_str!("templates/page.html")),
+    (INDEX_TEMPLATE, include_str!("templates/index.html")),
+    ("theme.html", include_str!("templates/theme.html")),
+    (
+        "live-reload.html",
+        include_str!("templates/live-reload.html"),
+    ),
+];
+
+/// The layouts pages and the index are rendered with.
+///
+/// These are the built-in templates, overridden by any file of the same name
+/// in the templates directory, which may also add partials and layouts of
+/// its own. Templates use Jinja syntax, so they can include and extend each
+/// other. The `[site]` table of the configuration is available to every
+/// template as `site`.
+#[derive(Debug, Clone)]
+pub struct Templates {
+    env: Environment<'static>,
+    dir: Option<PathBuf>,
+    site: toml::Table,
+    fingerprint: String,
+}
+
+/// What a page template is rendered with
+#[derive(Debug, Clone, Copy)]
+pub struct PageContext<'a> {
+    /// The page title
+    pub title: &'a str,
+
+    /// The rendered markdown
+    pub content: &'a str,
+
+    /// Table of contents to show above the content, if any
+    pub toc: &'a str,
+
+    /// The site navigation, if any
+    pub nav: &'a str,
+
+    /// Extra CSS, such as the code highlighting theme
+    pub styles: &'a str,
+
+    /// Metadata from the page's front matter
+    pub meta: &'a PageMeta,
+
+    /// When the page's source was last changed
+    pub last_modified: Option<SystemTime>,
+
+    /// Whether to include the live reload script
+    pub live_reload: bool,
+}
+
+/// A page as listed in the index template's `pages`
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexEntry<'a> {
+    /// The page title
+    pub title: &'a str,
+
+    /// Path of the page relative to the site root
+    pub url: String,
+
+    /// Metadata from the page's front matter
+    pub meta: &'a PageMeta,
+}
+
+impl Default for Templates {
+    /// The built-in templates, with an empty `site`
+    fn default() -> Self {
+        Self::load(None, &toml::Table::new()).expect("built-in templates are valid")
+    }
+}
+
+impl Templates {
+    /// Loads the built-in templates and any in `dir`, checking their syntax
+    pub fn load(dir: Option<&Path>, site: &toml::Table) -> Result<Self> {
+        let mut env = Environment::new();
+        let mut sources = Vec::new();
+        for (name, source) in BUILTIN_TEMPLATES {
+            env.add_template(name, source)
+                .with_context(|| format!("Invalid built-in template: {}", name))?;
+            sources.push((name.to_string(), source.to_string()));
+        }
+
+        let dir = match dir {
+            Some(dir) => Some(
+                dir.canonicalize()
+                    .with_context(|| format!("Templates directory not found: {}", dir.display()))?,
+            ),
+            None => None,
+        };
+        if let Some(dir) = &dir {
+            let mut files = Vec::new();
+            collect_template_files(dir, &mut files)?;
+            for path in files {
+                let name = template_name(&path, dir);
+                let source = fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read template: {}", path.display()))?;
+                env.add_template_owned(name.clone(), source.clone())
+                    .with_context(|| format!("Invalid template: {}", path.display()))?;
+                sources.push((name, source));
+            }
+        }
+
+        let site_json = serde_json::to_string(site)?;
+        env.add_global("site", Value::from(Serde(site)));
+        let fingerprint = fingerprint(
+            sources
+                .iter()
+                .flat_map(|(name, source)| [name.as_bytes(), source.as_bytes()])
+                .chain([site_json.as_bytes()]),
+        );
+
+        Ok(Self {
+            env,
+            dir,
+            site: site.clone(),
+            fingerprint,
+        })
+    }
+
+    /// Loads the templates again from the same directory, after they changed
+    pub fn reload(&self) -> Result<Self> {
+        Self::load(self.dir.as_deref(), &self.site)
+    }
+
+    /// The resolved templates directory, if there is one
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// Checks whether a file belongs to the templates directory
+    pub fn contains(&self, path: &Path) -> bool {
+        self.dir.as_deref().is_some_and(|dir| path.starts_with(dir))
+    }
+
+    /// Identifies the templates and `site` values, changing whenever they do
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    /// Renders a complete HTML page with the template its front matter
+    /// names, or the page template
+    pub fn render_page(&self, page: &PageContext) -> Result<String> {
+        let name = match &page.meta.template {
+            Some(name) if Path::new(name).extension().is_none() => format!("{}.html", name),
+            Some(name) => name.clone(),
+            None => PAGE_TEMPLATE.to_string(),
+        };
+        self.render(
+            &name,
+            context! {
+                title => page.title,
+                content => Value::from_safe_string(page.content.to_string()),
+                toc => Value::from_safe_string(page.toc.to_string()),
+                nav => Value::from_safe_string(page.nav.to_string()),
+                styles => Value::from_safe_string(page.styles.to_string()),
+                meta => Value::from(Serde(page.meta)),
+                last_modified => page.last_modified.map(format_timestamp),
+                live_reload => page.live_reload,
+            },
+        )
+    }
+
+    /// Renders the page listing, given as HTML list items and as `pages`
+    pub fn render_index(
+        &self,
+        listing: &str,
+        pages: &[IndexEntry],
+        live_reload: bool,
+    ) -> Result<String> {
+        self.render(
+            INDEX_TEMPLATE,
+            context! {
+                listing => Value::from_safe_string(listing.to_string()),
+                pages => Value::from(Serde(pages)),
+                live_reload,
+            },
+        )
+    }
+
+    fn render(&self, name: &str, context: Value) -> Result<String> {
+        let template = self
+            .env
+            .get_template(name)
+            .map_err(|e| anyhow!("{:#}", e))
+            .with_context(|| format!("Template not found: {}", name))?;
+        template
+            .render(context)
+            .map_err(|e| anyhow!("{:#}", e))
+            .with_context(|| format!("Failed to render template: {}", name))
+    }
+}
+
+/// Collects the template files in the templates directory, skipping hidden
+/// ones and files without a template extension
+fn collect_template_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read templates directory: {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.file_name().is_some_and(is_hidden) {
+            continue;
+        }
+        if path.is_dir() {
+            collect_template_files(&path, files)?;
+        } else if is_template_file(&path) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(())
+}
+
+/// Checks whether a file has one of the template extensions
+fn is_template_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| TEMPLATE_EXTENSIONS.contains(&ext))
+}
+
+/// The name a template file is referred to by: its path relative to the
+/// templates directory, with forward slashes
+fn template_name(path: &Path, dir: &Path) -> String {
+    let rel_path = path.strip_prefix(dir).unwrap_or(path);
+    rel_path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Formats a time as an RFC 3339 timestamp in UTC, like `2024-11-13T09:30:00Z`
+pub fn format_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since the epoch, after Howard Hinnant's `civil_from_days`
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    fn page<'a>(meta: &'a PageMeta, live_reload: bool) -> PageContext<'a> {
+        PageContext {
+            title: "Test <Title>",
+            content: "<p>Test content</p>",
+            toc: "",
+            nav: "<nav class=\"site-nav\"></nav>",
+            styles: "",
+            meta,
+            last_modified: Some(UNIX_EPOCH + Duration::from_secs(1_731_490_200)),
+            live_reload,
+        }
+    }
+
+    #[test]
+    fn test_builtin_page_template() -> Result<()> {
+        let templates = Templates::default();
+        let meta = PageMeta {
+            description: Some("A \"test\" page".to_string()),
+            tags: vec!["a".to_string(), "b".to_string()],
+            ..PageMeta::default()
+        };
+
+        let html = templates.render_page(&page(&meta, true))?;
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Test &lt;Title&gt;</title>"));
+        assert!(html.contains("<p>Test content</p>"));
+        assert!(html.contains("<nav class=\"site-nav\"></nav>"));
+        assert!(html.contains("<meta name=\"description\" content=\"A &quot;test&quot; page\">"));
+        assert!(html.contains("<meta name=\"keywords\" content=\"a, b\">"));
+        assert!(html.contains("EventSource"));
+        assert!(html.contains("prefers-color-scheme: dark"));
+        assert!(html.contains("class=\"theme-toggle\""));
+        assert!(html.trim_end().ends_with("</html>"));
+
+        let html = templates.render_page(&page(&PageMeta::default(), false))?;
+        assert!(!html.contains("EventSource"));
+        assert!(!html.contains("<meta name=\"description\""));
+        Ok(())
+    }
+
+    #[test]
+    fn test_templates_directory() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        fs::create_dir(dir.join("partials"))?;
+        fs::write(
+            dir.join("base.html"),
+            "<html><body>{% include \"partials/header.html\" %}{% block body %}{% endblock %}</body></html>",
+        )?;
+        fs::write(
+            dir.join("partials").join("header.html"),
+            "<header>{{ site.title }}</header>",
+        )?;
+        fs::write(
+            dir.join("wide.html"),
+            "{% extends \"page.html\" %}{% block body %}<main class=\"wide\">{{ content }}</main>\
+             <p>{{ meta.owner }} {{ last_modified }}</p>{% endblock %}",
+        )?;
+        // Files that aren't templates are left alone
+        fs::write(dir.join("logo.png"), [0x89, b'P', b'N', b'G', 0xff, 0xfe])?;
+        fs::write(dir.join("base.html~"), "{% block body %}")?;
+        fs::write(dir.join("partials").join("footer.j2"), "<footer></footer>")?;
+
+        let site: toml::Table = toml::from_str("title = \"Handbook\"")?;
+        let templates = Templates::load(Some(dir), &site)?;
+        assert!(templates.env.get_template("partials/footer.j2").is_ok());
+        assert!(templates.env.get_template("logo.png").is_err());
+
+        // The page template extends the overridden base
+        let html = templates.render_page(&page(&PageMeta::default(), false))?;
+        assert!(html.starts_with("<html><body><header>Handbook</header>"));
+        assert!(html.contains("<p>Test content</p>"));
+
+        // Front matter picks a layout, which sees the page's extra keys
+        let meta: PageMeta = serde_yaml::from_str("template: wide\nowner: nclack")?;
+        let html = templates.render_page(&page(&meta, false))?;
+        assert!(html.contains("<main class=\"wide\"><p>Test content</p></main>"));
+        assert!(html.contains("<p>nclack 2024-11-13T09:30:00Z</p>"));
+
+        let meta = PageMeta {
+            template: Some("missing".to_string()),
+            ..PageMeta::default()
+        };
+        let err = templates.render_page(&page(&meta, false)).unwrap_err();
+        assert!(err.to_string().contains("missing.html"));
+
+        // Changes are picked up on reload, and invalid templates are rejected
+        let fingerprint = templates.fingerprint().to_string();
+        fs::write(
+            dir.join("partials").join("header.html"),
+            "<header>New</header>",
+        )?;
+        let reloaded = templates.reload()?;
+        assert_ne!(reloaded.fingerprint(), fingerprint);
+        assert!(reloaded.contains(&dir.canonicalize()?.join("wide.html")));
+
+        fs::write(dir.join("wide.html"), "{% block body %}")?;
+        let err = templates.reload().unwrap_err();
+        assert!(err.to_string().contains("wide.html"));
+
+        assert!(Templates::load(Some(&dir.join("missing")), &site).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_template() -> Result<()> {
+        let templates = Templates::default();
+        let meta = PageMeta::default();
+        let pages = [IndexEntry {
+            title: "Intro",
+            url: "intro.html".to_string(),
+            meta: &meta,
+        }];
+
+        let html = templates.render_index("<li>listing</li>\n", &pages, false)?;
+        assert!(html.contains("<title>Markdown Documentation</title>"));
+        assert!(html.contains("<ul class=\"file-list\">\n<li>listing</li>\n</ul>"));
+        assert!(!html.contains("EventSource"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951_782_400 + 3_661);
+        assert_eq!(format_timestamp(leap_day), "2000-02-29T01:01:01Z");
+    }
+}
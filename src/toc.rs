@@ -0,0 +1,149 @@
+use std::{collections::HashSet, fmt::Write};
+
+/// A heading on a rendered page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    /// Heading level, 1 through 6
+    pub level: u32,
+
+    /// The `id` attribute, used as the anchor target
+    pub id: String,
+
+    /// Plain text of the heading
+    pub text: String,
+}
+
+/// Generates unique heading ids the way GitHub does: `Usage` and a second
+/// `Usage` become `usage` and `usage-1`
+#[derive(Debug, Default)]
+pub struct Slugger {
+    used: HashSet<String>,
+}
+
+impl Slugger {
+    /// Returns a unique id for a heading with the given text
+    pub fn slug(&mut self, text: &str) -> String {
+        let base = slugify(text);
+        let mut slug = base.clone();
+        let mut suffix = 1;
+        while self.used.contains(&slug) {
+            slug = format!("{}-{}", base, suffix);
+            suffix += 1;
+        }
+        self.used.insert(slug.clone());
+        slug
+    }
+
+    /// Marks an explicitly given id as taken so generated ids avoid it
+    pub fn reserve(&mut self, id: &str) {
+        self.used.insert(id.to_string());
+    }
+}
+
+/// Converts heading text to an anchor: lowercase, punctuation dropped and
+/// spaces replaced with hyphens
+pub fn slugify(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            _ if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Renders a nested list of links to the headings.
+///
+/// Nesting is relative to the shallowest heading, so a page without a
+/// level-one heading doesn't get an empty outer list.
+pub fn table_of_contents(headings: &[Heading]) -> String {
+    let Some(min_level) = headings.iter().map(|h| h.level).min() else {
+        return String::new();
+    };
+
+    let mut html = String::from("<nav class=\"toc\">\n");
+    let mut depth = 0;
+    for heading in headings {
+        let level = heading.level - min_level + 1;
+        if level > depth {
+            // Open lists down to this heading's level
+            while depth < level {
+                html.push_str("<ul>\n<li>");
+                depth += 1;
+            }
+        } else {
+            // Close deeper lists, then start a sibling item
+            while depth > level {
+                html.push_str("</li>\n</ul>\n");
+                depth -= 1;
+            }
+            html.push_str("</li>\n<li>");
+        }
+        let _ = write!(
+            html,
+            "<a href=\"#{}\">{}</a>",
+            crate::escape_html(&heading.id),
+            crate::escape_html(&heading.text)
+        );
+    }
+    while depth > 0 {
+        html.push_str("</li>\n</ul>\n");
+        depth -= 1;
+    }
+    html.push_str("</nav>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heading(level: u32, id: &str) -> Heading {
+        Heading {
+            level,
+            id: id.to_string(),
+            text: id.to_uppercase(),
+        }
+    }
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Hello World"), "hello-world");
+        assert_eq!(slugify("  What's new? "), "whats-new");
+        assert_eq!(slugify("snake_case & kebab-case"), "snake_case--kebab-case");
+        assert_eq!(slugify("Überblick 2.0"), "überblick-20");
+        assert_eq!(slugify("`code` *emphasis*"), "code-emphasis");
+    }
+
+    #[test]
+    fn test_slugger_deduplicates() {
+        let mut slugger = Slugger::default();
+        slugger.reserve("usage-1");
+
+        assert_eq!(slugger.slug("Usage"), "usage");
+        assert_eq!(slugger.slug("Usage"), "usage-2");
+        assert_eq!(slugger.slug("usage"), "usage-3");
+        assert_eq!(slugger.slug("Other"), "other");
+    }
+
+    #[test]
+    fn test_table_of_contents() {
+        assert_eq!(table_of_contents(&[]), "");
+
+        let toc = table_of_contents(&[
+            heading(2, "a"),
+            heading(3, "b"),
+            heading(4, "c"),
+            heading(2, "d"),
+        ]);
+        assert_eq!(
+            toc,
+            "<nav class=\"toc\">\n<ul>\n<li><a href=\"#a\">A</a><ul>\n<li><a href=\"#b\">B</a><ul>\n<li><a href=\"#c\">C</a></li>\n</ul>\n</li>\n</ul>\n</li>\n<li><a href=\"#d\">D</a></li>\n</ul>\n</nav>\n"
+        );
+        assert_eq!(toc.matches("<ul>").count(), toc.matches("</ul>").count());
+        assert_eq!(toc.matches("<li>").count(), toc.matches("</li>").count());
+    }
+}
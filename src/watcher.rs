@@ -1,27 +1,145 @@
 use anyhow::{Context, Result};
-use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{
+    new_debouncer_opt, DebounceEventResult, DebouncedEvent, DebouncedEventKind, Debouncer,
+};
 use std::{
+    fs,
     path::{Path, PathBuf},
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::sync::broadcast;
 
-use crate::markdown::render_markdown_file;
+use crate::{
+    assets::{is_asset, sync_asset},
+    cache::RenderCache,
+    filter::{ContentFilter, FilterOptions},
+    generate_index_html,
+    live::{BuildError, Change, LiveEvent},
+    load_render_cache,
+    markdown::{remove_rendered_file, MarkdownOptions},
+    memory::PageStore,
+    nav::page_href,
+    render_pages,
+    site::Site,
+    templates::Templates,
+    PageRender, INDEX_LISTING_FILE,
+};
+
+/// What the file watcher keeps between batches of changes
+struct WatchedSite {
+    /// The page model, so the watcher can tell when the index is stale
+    site: Site,
+    templates: Templates,
+    /// Pages whose output is up to date, shared with the next server start
+    cache: RenderCache,
+}
 
-/// Sets up a file watcher for markdown files in the content directory
+/// Sets up a file watcher for markdown files and assets in the content
+/// directory, and for the templates directory if there is one.
+///
+/// Changes are handled in batches once they have settled for `debounce`.
+/// Files the filter leaves out are ignored.
 pub fn setup_file_watcher(
     content_dir: PathBuf,
     output_dir: PathBuf,
-    tx: Arc<broadcast::Sender<PathBuf>>,
+    options: MarkdownOptions,
+    filter: FilterOptions,
+    templates: Templates,
+    debounce: Duration,
+    tx: Arc<broadcast::Sender<LiveEvent>>,
+) -> Result<()> {
+    // Event paths are absolute, so resolve the directories the same way to compare against them
+    let content_dir = content_dir
+        .canonicalize()
+        .with_context(|| format!("Content directory not found: {}", content_dir.display()))?;
+    let output_dir = output_dir.canonicalize().unwrap_or(output_dir);
+
+    let filter = ContentFilter::new(&content_dir, &filter)?;
+
+    let mut watched = WatchedSite {
+        site: Site::scan(&filter, &options).context("Failed to scan content directory")?,
+        cache: load_render_cache(&output_dir, &options, &templates, true)?,
+        templates,
+    };
+
+    let mut watched_dirs = vec![content_dir];
+    watched_dirs.extend(watched.templates.dir().map(Path::to_path_buf));
+    let watcher = create_watcher(debounce, move |paths| {
+        handle_fs_event(paths, &filter, &output_dir, &options, &mut watched, &tx);
+    })?;
+    start_watching(watcher, &watched_dirs)
+}
+
+/// Sets up a file watcher that invalidates pages rendered in memory
+pub fn setup_memory_watcher(
+    store: Arc<PageStore>,
+    debounce: Duration,
+    tx: Arc<broadcast::Sender<LiveEvent>>,
 ) -> Result<()> {
-    let mut watcher =
-        create_watcher(output_dir, tx.clone()).context("Failed to create file watcher")?;
+    let content_dir = store.content_dir().to_path_buf();
+    let templates_dir = store.templates().dir().map(Path::to_path_buf);
+
+    let mut watched_dirs = vec![content_dir.clone()];
+    watched_dirs.extend(templates_dir);
+    let watcher = create_watcher(debounce, move |paths| {
+        handle_memory_event(paths, &content_dir, &store, &tx);
+    })?;
+    start_watching(watcher, &watched_dirs)
+}
+
+/// Creates a new file watcher that passes batches of changed paths to
+/// `handler`, once each path has gone `debounce` without changing
+fn create_watcher(
+    debounce: Duration,
+    mut handler: impl FnMut(Vec<PathBuf>) + Send + 'static,
+) -> Result<Debouncer<RecommendedWatcher>> {
+    let notify_config = notify::Config::default()
+        .with_compare_contents(true) // Detect content changes
+        .with_poll_interval(Duration::from_secs(1));
+    // Batch mode hands over every path that settled by the same deadline at once
+    let config = notify_debouncer_mini::Config::default()
+        .with_timeout(debounce)
+        .with_batch_mode(true)
+        .with_notify_config(notify_config);
+
+    new_debouncer_opt(config, move |res: DebounceEventResult| match res {
+        Ok(events) => {
+            let paths = settled_paths(events);
+            if !paths.is_empty() {
+                handler(paths);
+            }
+        }
+        Err(e) => eprintln!("Watch error: {}", e),
+    })
+    .context("Failed to create file watcher")
+}
+
+/// Picks the files to handle from a batch of debounced events.
+///
+/// Files still being written are left for the event that follows once they
+/// settle, and directories are skipped since their files have events of their
+/// own. Each path appears once, in a stable order.
+fn settled_paths(events: Vec<DebouncedEvent>) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = events
+        .into_iter()
+        .filter(|event| event.kind == DebouncedEventKind::Any && !event.path.is_dir())
+        .map(|event| event.path)
+        .collect();
+    paths.sort();
+    paths.dedup();
+    paths
+}
 
-    // Start watching content directory
-    watcher
-        .watch(&content_dir, RecursiveMode::Recursive)
-        .context("Failed to start watching content directory")?;
+/// Watches the given directories for as long as the runtime is alive
+fn start_watching(mut watcher: Debouncer<RecommendedWatcher>, dirs: &[PathBuf]) -> Result<()> {
+    for dir in dirs {
+        watcher
+            .watcher()
+            .watch(dir, RecursiveMode::Recursive)
+            .with_context(|| format!("Failed to start watching directory: {}", dir.display()))?;
+    }
 
     // Keep watcher alive by moving it into a spawned task
     tokio::spawn(async move {
@@ -34,64 +152,313 @@ pub fn setup_file_watcher(
     Ok(())
 }
 
-/// Creates a new file watcher with the specified configuration
-fn create_watcher(
-    output_dir: PathBuf,
-    tx: Arc<broadcast::Sender<PathBuf>>,
-) -> Result<RecommendedWatcher> {
-    let config = Config::default()
-        .with_compare_contents(true) // Detect content changes
-        .with_poll_interval(Duration::from_secs(1));
-
-    RecommendedWatcher::new(
-        move |res: Result<Event, notify::Error>| {
-            handle_fs_event(res, &output_dir, &tx);
-        },
-        config,
-    )
-    .context("Failed to create watcher with config")
-}
-
-/// Handles file system events for markdown files
+/// Handles a batch of changed markdown files, assets and templates, rendering
+/// each affected page once and notifying browsers once the output is up to date
 fn handle_fs_event(
-    res: Result<Event, notify::Error>,
+    paths: Vec<PathBuf>,
+    filter: &ContentFilter,
     output_dir: &Path,
-    tx: &Arc<broadcast::Sender<PathBuf>>,
+    options: &MarkdownOptions,
+    watched: &mut WatchedSite,
+    tx: &Arc<broadcast::Sender<LiveEvent>>,
 ) {
-    match res {
-        Ok(event) => {
-            // Filter events to only handle relevant ones
-            if !is_relevant_event(&event) {
-                return;
-            }
-
-            for path in event.paths {
-                if path.extension().map_or(false, |ext| ext == "md") {
-                    // Render markdown to HTML
-                    if let Err(e) = render_markdown_file(&path, output_dir) {
-                        eprintln!("Error rendering markdown: {}", e);
-                    }
-                    // Notify clients
-                    if let Err(e) = tx.send(path) {
-                        eprintln!("Error broadcasting change: {}", e);
+    let WatchedSite {
+        site,
+        templates,
+        cache,
+    } = watched;
+    let started = Instant::now();
+    let content_dir = filter.content_dir();
+    let mut pages_changed = false;
+    let mut changed = Vec::new();
+    let mut failed = Vec::new();
+    let mut to_render = Vec::new();
+    let mut errors = Vec::new();
+    let mut template_paths = Vec::new();
+
+    for path in paths {
+        if templates.contains(&path) {
+            template_paths.push(path);
+            continue;
+        }
+
+        // Ignore our own output when it lives inside the content directory,
+        // and anything left out of the site
+        if path.starts_with(output_dir) || !filter.is_included(&path) {
+            continue;
+        }
+
+        if options.is_markdown(&path) {
+            if path.is_file() {
+                pages_changed |= site.update(&path);
+                to_render.push(path.clone());
+                changed.push(path);
+            } else if !path.exists() {
+                // Deleted, or the old name of a rename: drop the stale page
+                pages_changed |= site.remove(&path);
+                cache.forget(path.strip_prefix(content_dir).unwrap_or(&path));
+                match remove_rendered_file(&path, content_dir, output_dir, options) {
+                    Ok(()) => changed.push(path),
+                    Err(e) => {
+                        eprintln!("Error removing page: {}", e);
+                        let url = page_href(&path, content_dir, options);
+                        errors.push(BuildError::new(&path, content_dir, url, &e));
+                        failed.push(path);
                     }
                 }
             }
+        } else if path
+            .strip_prefix(content_dir)
+            .is_ok_and(|rel_path| is_asset(rel_path, options))
+        {
+            // Mirror the asset into the output directory
+            match sync_asset(&path, content_dir, output_dir) {
+                Ok(()) => changed.push(path),
+                Err(e) => {
+                    eprintln!("Error syncing asset: {}", e);
+                    errors.push(BuildError::new(&path, content_dir, None, &e));
+                    failed.push(path);
+                }
+            }
+        }
+    }
+
+    if changed.is_empty() && failed.is_empty() && template_paths.is_empty() {
+        return;
+    }
+    broadcast(tx, LiveEvent::Building);
+
+    // Every page uses the templates. If they no longer load, the old ones
+    // are kept and the error is shown instead.
+    let mut templates_changed = false;
+    if let Some(path) = template_paths.first() {
+        match templates.reload() {
+            Ok(reloaded) => {
+                *templates = reloaded;
+                templates_changed = true;
+                changed.extend(template_paths.iter().cloned());
+                // Output rendered with the old templates can't be reused
+                *cache =
+                    load_render_cache(output_dir, options, templates, true).unwrap_or_default();
+            }
+            Err(e) => {
+                eprintln!("Error loading templates: {:#}", e);
+                let templates_dir = templates.dir().unwrap_or(content_dir);
+                errors.push(BuildError::new(path, templates_dir, None, &e));
+            }
+        }
+    }
+
+    // New, removed or retitled pages make the index and every page's
+    // navigation stale, and new templates change every page
+    if pages_changed || templates_changed {
+        if let Err(e) = generate_index_html(
+            output_dir,
+            site.pages(),
+            content_dir,
+            options,
+            templates,
+            true,
+        ) {
+            eprintln!("Error updating index: {}", e);
+            let listing = Path::new(INDEX_LISTING_FILE);
+            errors.push(BuildError::new(listing, content_dir, None, &e));
+        }
+        to_render = site.markdown_files();
+    }
+
+    // Pages that render to the same file as another are reported instead of
+    // overwriting it. Any page added or removed may start or end a conflict.
+    for (path, e) in site.conflicts() {
+        if pages_changed || to_render.iter().any(|p| p == path) {
+            eprintln!("Error rendering markdown: {}", e);
+            let url = page_href(path, content_dir, options);
+            errors.push(BuildError::new(path, content_dir, url, &e));
+            failed.push(path.to_path_buf());
         }
-        Err(e) => eprintln!("Watch error: {}", e),
     }
+    to_render.retain(|path| !failed.contains(path));
+
+    // Render markdown to HTML
+    let results = render_pages(
+        site,
+        &to_render,
+        content_dir,
+        output_dir,
+        templates,
+        true,
+        cache,
+    );
+    let mut rendered = Vec::new();
+    let mut rendered_count = 0;
+    for (path, result) in to_render.iter().zip(results) {
+        match result {
+            Ok(render) => {
+                rendered_count += usize::from(!matches!(render, PageRender::Unchanged(_)));
+                rendered.push((path, render.output().to_path_buf()));
+            }
+            Err(e) => {
+                eprintln!("Error rendering markdown: {}", e);
+                let url = page_href(path, content_dir, options);
+                errors.push(BuildError::new(path, content_dir, url, &e));
+                failed.push(path.clone());
+            }
+        }
+    }
+    if let Err(e) = cache.save(output_dir) {
+        eprintln!("Error saving render cache: {}", e);
+    }
+
+    // Notify clients once the output is up to date, errors first so they
+    // arrive before any reload. Changed pages are sent along so they can be
+    // patched in place, while pages that failed keep their old content and
+    // show the error instead.
+    for error in errors {
+        broadcast(tx, LiveEvent::Error(error));
+    }
+    let changes: Vec<Change> = changed
+        .into_iter()
+        .filter(|path| !failed.contains(path))
+        .map(|path| match page_href(&path, content_dir, options) {
+            Some(url) if options.is_markdown(&path) => {
+                let html = rendered
+                    .iter()
+                    .find(|(source, _)| **source == path)
+                    .and_then(|(_, output_path)| fs::read_to_string(output_path).ok());
+                Change::page(path, url, html.as_deref(), pages_changed)
+            }
+            _ => Change::new(path),
+        })
+        .collect();
+    if !changes.is_empty() {
+        broadcast(tx, LiveEvent::Changed(changes));
+    }
+    broadcast(
+        tx,
+        LiveEvent::Built {
+            rendered: rendered_count,
+            duration: started.elapsed(),
+        },
+    );
+}
+
+/// Handles a batch of changes when pages are rendered in memory: changed
+/// pages are dropped from the cache, changed templates are reloaded, and
+/// assets are served from the content directory as they are
+fn handle_memory_event(
+    paths: Vec<PathBuf>,
+    content_dir: &Path,
+    store: &PageStore,
+    tx: &broadcast::Sender<LiveEvent>,
+) {
+    let started = Instant::now();
+    let options = store.options();
+    let templates = store.templates();
+    let (template_paths, paths): (Vec<PathBuf>, Vec<PathBuf>) =
+        paths.into_iter().partition(|path| templates.contains(path));
+    let paths: Vec<PathBuf> = paths
+        .into_iter()
+        .filter(|path| {
+            let is_page = options.is_markdown(path);
+            (is_page
+                || path
+                    .strip_prefix(content_dir)
+                    .is_ok_and(|rel_path| is_asset(rel_path, options)))
+                && store.filter().is_included(path)
+        })
+        .collect();
+    if paths.is_empty() && template_paths.is_empty() {
+        return;
+    }
+    broadcast(tx, LiveEvent::Building);
+
+    let mut rendered = 0;
+    let mut changes = Vec::new();
+    if let Some(path) = template_paths.first() {
+        match templates.reload() {
+            Ok(templates) => {
+                store.set_templates(templates);
+                changes.extend(template_paths.iter().cloned().map(Change::new));
+            }
+            Err(e) => {
+                eprintln!("Error loading templates: {:#}", e);
+                let templates_dir = templates.dir().unwrap_or(content_dir);
+                let error = BuildError::new(path, templates_dir, None, &e);
+                broadcast(tx, LiveEvent::Error(error));
+            }
+        }
+    }
+    let mut any_pages_changed = false;
+    let mut conflicting = Vec::new();
+    for path in paths {
+        if !options.is_markdown(&path) {
+            changes.push(Change::new(path));
+            continue;
+        }
+
+        let pages_changed = store.invalidate(&path);
+        any_pages_changed |= pages_changed;
+
+        // Another page renders to the same file, and is served instead
+        if let Err(e) = store.check_output(&path) {
+            eprintln!("Error rendering markdown: {}", e);
+            let url = page_href(&path, content_dir, options);
+            broadcast(
+                tx,
+                LiveEvent::Error(BuildError::new(&path, content_dir, url, &e)),
+            );
+            conflicting.push(path);
+            continue;
+        }
+
+        // Render the page right away so it can be patched in open browsers
+        let Some(url) = page_href(&path, content_dir, options) else {
+            continue;
+        };
+        match store.render(Path::new(&url)) {
+            Some(Ok(html)) => {
+                rendered += 1;
+                changes.push(Change::page(path, url, Some(&html), pages_changed));
+            }
+            Some(Err(e)) => {
+                eprintln!("Error rendering markdown: {}", e);
+                let error = BuildError::new(&path, content_dir, Some(url), &e);
+                broadcast(tx, LiveEvent::Error(error));
+            }
+            // The page was removed
+            None => changes.push(Change::page(path, url, None, pages_changed)),
+        }
+    }
+
+    // Any page added or removed may start a conflict between other pages
+    if any_pages_changed {
+        for (path, e) in store.conflicts() {
+            if !conflicting.contains(&path) {
+                eprintln!("Error rendering markdown: {}", e);
+                let url = page_href(&path, content_dir, options);
+                broadcast(
+                    tx,
+                    LiveEvent::Error(BuildError::new(&path, content_dir, url, &e)),
+                );
+            }
+        }
+    }
+    if !changes.is_empty() {
+        broadcast(tx, LiveEvent::Changed(changes));
+    }
+    broadcast(
+        tx,
+        LiveEvent::Built {
+            rendered,
+            duration: started.elapsed(),
+        },
+    );
 }
 
-/// Determines if a file system event is relevant for processing
-fn is_relevant_event(event: &Event) -> bool {
-    use notify::event::{CreateKind, ModifyKind, RemoveKind};
-    matches!(
-        event.kind,
-        EventKind::Create(CreateKind::File)
-            | EventKind::Modify(ModifyKind::Data(_))
-            | EventKind::Modify(ModifyKind::Name(_))
-            | EventKind::Remove(RemoveKind::File)
-    )
+/// Sends an event to connected browsers
+fn broadcast(tx: &broadcast::Sender<LiveEvent>, event: LiveEvent) {
+    // Sending only fails when no browser is connected
+    let _ = tx.send(event);
 }
 
 #[cfg(test)]
@@ -101,36 +468,80 @@ mod tests {
     use tempfile::TempDir;
     use tokio::time::sleep;
 
-    #[tokio::test]
-    async fn test_watcher_file_creation() -> Result<()> {
+    /// Debounce window for tests, short enough to keep them quick
+    const DEBOUNCE: Duration = Duration::from_millis(50);
+
+    /// Creates empty content and output directories in a new temporary directory
+    fn test_dirs() -> Result<(TempDir, PathBuf, PathBuf)> {
         let temp_dir = TempDir::new()?;
         let content_dir = temp_dir.path().join("content");
         let output_dir = temp_dir.path().join("output");
-
         fs::create_dir_all(&content_dir)?;
         fs::create_dir_all(&output_dir)?;
+        Ok((temp_dir, content_dir, output_dir))
+    }
 
-        let (tx, mut rx) = broadcast::channel(16);
-        let tx = Arc::new(tx);
+    /// Starts watching the content directory with the default options,
+    /// returning a receiver for the watcher's events
+    fn start_watcher(
+        content_dir: &Path,
+        output_dir: &Path,
+    ) -> Result<broadcast::Receiver<LiveEvent>> {
+        start_watcher_with(content_dir, output_dir, Templates::default(), DEBOUNCE)
+    }
+
+    /// Starts watching the content directory with the given templates and debounce window
+    fn start_watcher_with(
+        content_dir: &Path,
+        output_dir: &Path,
+        templates: Templates,
+        debounce: Duration,
+    ) -> Result<broadcast::Receiver<LiveEvent>> {
+        let (tx, rx) = broadcast::channel(16);
+        setup_file_watcher(
+            content_dir.to_path_buf(),
+            output_dir.to_path_buf(),
+            MarkdownOptions::default(),
+            FilterOptions::default(),
+            templates,
+            debounce,
+            Arc::new(tx),
+        )?;
+        Ok(rx)
+    }
+
+    /// Receives the next batch of file changes, skipping build status events
+    async fn next_change(
+        rx: &mut broadcast::Receiver<LiveEvent>,
+    ) -> Result<Vec<Change>, broadcast::error::RecvError> {
+        loop {
+            if let LiveEvent::Changed(changes) = rx.recv().await? {
+                return Ok(changes);
+            }
+        }
+    }
 
-        // Setup watcher
-        setup_file_watcher(content_dir.clone(), output_dir.clone(), tx)?;
+    #[tokio::test]
+    async fn test_watcher_file_creation() -> Result<()> {
+        let (_temp_dir, content_dir, output_dir) = test_dirs()?;
+        let mut rx = start_watcher(&content_dir, &output_dir)?;
 
         // Create a new markdown file
         let test_file = content_dir.join("test.md");
         fs::write(&test_file, "# Test")?;
 
         // Wait for the watcher to process the file
-        let received_path = tokio::select! {
+        let received = tokio::select! {
             _ = sleep(Duration::from_secs(2)) => {
                 panic!("Timeout waiting for file change event");
             }
-            result = rx.recv() => {
+            result = next_change(&mut rx) => {
                 result.expect("Failed to receive file change event")
             }
         };
 
-        assert_eq!(received_path.canonicalize()?, test_file.canonicalize()?);
+        let test_file = test_file.canonicalize()?;
+        assert!(received.iter().any(|change| change.path == test_file));
 
         // Check if HTML was generated
         let html_file = output_dir.join("test.html");
@@ -141,25 +552,15 @@ mod tests {
 
     #[tokio::test]
     async fn test_watcher_file_modification() -> Result<()> {
-        let temp_dir = TempDir::new()?;
-        let content_dir = temp_dir.path().join("content");
-        let output_dir = temp_dir.path().join("output");
-
-        fs::create_dir_all(&content_dir)?;
-        fs::create_dir_all(&output_dir)?;
-
-        let (tx, mut rx) = broadcast::channel(16);
-        let tx = Arc::new(tx);
-
-        // setup watcher
-        setup_file_watcher(content_dir.clone(), output_dir.clone(), tx.clone())?;
+        let (_temp_dir, content_dir, output_dir) = test_dirs()?;
+        let mut rx = start_watcher(&content_dir, &output_dir)?;
 
         // create initial file and ensure it's synced to disk
         let test_file = content_dir.join("test.md");
         fs::write(&test_file, "# initial content")?;
 
         // wait for initial file creation to be processed
-        let _ = rx.recv().await;
+        let _ = next_change(&mut rx).await;
 
         // add delay to ensure initial rendering completes
         sleep(Duration::from_millis(100)).await;
@@ -168,16 +569,17 @@ mod tests {
         fs::write(&test_file, "# modified content")?;
 
         // wait for the modification event
-        let received_path = tokio::select! {
+        let received = tokio::select! {
             _ = sleep(Duration::from_secs(2)) => {
                 panic!("timeout waiting for file modification event");
             }
-            result = rx.recv() => {
+            result = next_change(&mut rx) => {
                 result.expect("failed to receive file modification event")
             }
         };
 
-        assert_eq!(received_path.canonicalize()?, test_file.canonicalize()?);
+        let test_file = test_file.canonicalize()?;
+        assert!(received.iter().any(|change| change.path == test_file));
 
         // add delay to ensure modification rendering completes
         sleep(Duration::from_millis(100)).await;
@@ -188,37 +590,346 @@ mod tests {
         Ok(())
     }
 
+    /// Waits for change events until `done` returns true
+    async fn wait_for(
+        rx: &mut broadcast::Receiver<LiveEvent>,
+        what: &str,
+        mut done: impl FnMut() -> bool,
+    ) {
+        tokio::select! {
+            _ = sleep(Duration::from_secs(2)) => {
+                panic!("Timeout waiting for {}", what);
+            }
+            _ = async {
+                while rx.recv().await.is_ok() {
+                    if done() {
+                        break;
+                    }
+                }
+            } => {}
+        }
+    }
+
+    #[tokio::test]
+    async fn test_watcher_file_removal_and_rename() -> Result<()> {
+        let (_temp_dir, content_dir, output_dir) = test_dirs()?;
+        let mut rx = start_watcher(&content_dir, &output_dir)?;
+
+        let old_file = content_dir.join("old_name.md");
+        let old_html = output_dir.join("old_name.html");
+        fs::write(&old_file, "# Moving page")?;
+        wait_for(&mut rx, "initial render", || old_html.exists()).await;
+
+        // Rename moves the rendered page
+        let new_file = content_dir.join("new_name.md");
+        let new_html = output_dir.join("new_name.html");
+        fs::rename(&old_file, &new_file)?;
+        wait_for(&mut rx, "rename", || {
+            !old_html.exists() && new_html.exists()
+        })
+        .await;
+
+        let index = fs::read_to_string(output_dir.join("index.html"))?;
+        assert!(index.contains("new_name.html"));
+        assert!(!index.contains("old_name.html"));
+
+        // Removal deletes the rendered page
+        fs::remove_file(&new_file)?;
+        wait_for(&mut rx, "removal", || !new_html.exists()).await;
+
+        sleep(Duration::from_millis(100)).await;
+        let index = fs::read_to_string(output_dir.join("index.html"))?;
+        assert!(!index.contains("new_name.html"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watcher_index_tracks_new_pages() -> Result<()> {
+        let (_temp_dir, content_dir, output_dir) = test_dirs()?;
+        fs::write(content_dir.join("existing.md"), "# Existing")?;
+
+        let mut rx = start_watcher(&content_dir, &output_dir)?;
+
+        // A page created while running shows up in the index, and in other
+        // pages' navigation, which is rendered after the index
+        fs::write(content_dir.join("added_later.md"), "# Added")?;
+        let link = "<a href=\"added_later.html\">Added</a>";
+        let existing_path = output_dir.join("existing.html");
+        wait_for(&mut rx, "navigation update", || {
+            fs::read_to_string(&existing_path).is_ok_and(|html| html.contains(link))
+        })
+        .await;
+
+        let index_path = output_dir.join("index.html");
+        let index = fs::read_to_string(&index_path)?;
+        assert!(index.contains(link));
+        assert!(index.contains("existing.html"));
+
+        // Pages rendered while running are reused on the next start
+        let cache_path = output_dir.join(crate::cache::CACHE_FILE);
+        wait_for(&mut rx, "render cache", || {
+            fs::read_to_string(&cache_path).is_ok_and(|cache| {
+                cache.contains("existing.md") && cache.contains("added_later.md")
+            })
+        })
+        .await;
+
+        // Retitling a page updates its index entry
+        fs::write(content_dir.join("existing.md"), "# Retitled")?;
+        wait_for(&mut rx, "index retitle", || {
+            fs::read_to_string(&index_path).is_ok_and(|index| index.contains("Retitled"))
+        })
+        .await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watcher_skips_excluded_files() -> Result<()> {
+        let (_temp_dir, content_dir, output_dir) = test_dirs()?;
+        fs::create_dir_all(content_dir.join("node_modules"))?;
+        fs::write(content_dir.join(".gitignore"), "scratch.md\n")?;
+
+        let mut rx = start_watcher(&content_dir, &output_dir)?;
+
+        fs::write(content_dir.join("node_modules").join("dep.md"), "# Dep")?;
+        fs::write(content_dir.join("scratch.md"), "# Scratch")?;
+        sleep(DEBOUNCE * 2).await;
+        fs::write(content_dir.join("page.md"), "# Page")?;
+
+        // Only the included page makes it into a batch
+        let mut changed = Vec::new();
+        tokio::select! {
+            _ = sleep(Duration::from_secs(2)) => {
+                panic!("Timeout waiting for page render");
+            }
+            _ = async {
+                while let Ok(event) = rx.recv().await {
+                    match event {
+                        LiveEvent::Changed(batch) => {
+                            changed.extend(batch.into_iter().map(|change| change.path))
+                        }
+                        LiveEvent::Built { .. } if output_dir.join("page.html").exists() => break,
+                        _ => {}
+                    }
+                }
+            } => {}
+        }
+
+        assert_eq!(changed, [content_dir.join("page.md").canonicalize()?]);
+        assert!(!output_dir.join("scratch.html").exists());
+        assert!(!output_dir.join("node_modules").exists());
+        let index = fs::read_to_string(output_dir.join("index.html"))?;
+        assert!(!index.contains("Scratch"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watcher_asset_sync() -> Result<()> {
+        let (_temp_dir, content_dir, output_dir) = test_dirs()?;
+        fs::create_dir_all(content_dir.join("images"))?;
+
+        let mut rx = start_watcher(&content_dir, &output_dir)?;
+
+        // Create an asset
+        let asset = content_dir.join("images").join("logo.svg");
+        fs::write(&asset, "<svg/>")?;
+
+        // The file may be copied more than once while it's being written
+        let copied = output_dir.join("images").join("logo.svg");
+        tokio::select! {
+            _ = sleep(Duration::from_secs(2)) => {
+                panic!("Timeout waiting for asset to be copied");
+            }
+            _ = async {
+                while let Ok(changes) = next_change(&mut rx).await {
+                    let asset = asset.canonicalize().ok();
+                    assert!(changes.iter().all(|change| Some(&change.path) == asset.as_ref()));
+                    if fs::read_to_string(&copied).is_ok_and(|s| s == "<svg/>") {
+                        break;
+                    }
+                }
+            } => {}
+        }
+
+        // Delete it and wait for the copy to disappear
+        fs::remove_file(&asset)?;
+        tokio::select! {
+            _ = sleep(Duration::from_secs(2)) => {
+                panic!("Timeout waiting for asset removal event");
+            }
+            _ = async {
+                while rx.recv().await.is_ok() {
+                    if !copied.exists() {
+                        break;
+                    }
+                }
+            } => {}
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watcher_reports_failed_asset_sync() -> Result<()> {
+        let (_temp_dir, content_dir, output_dir) = test_dirs()?;
+        // A directory in the way of the copy makes it fail
+        fs::create_dir_all(output_dir.join("logo.svg"))?;
+
+        let mut rx = start_watcher(&content_dir, &output_dir)?;
+        fs::write(content_dir.join("page.md"), "# Page")?;
+        fs::write(content_dir.join("logo.svg"), "<svg/>")?;
+
+        // The error comes ahead of the batch's changes, which leave out the asset
+        let events = tokio::time::timeout(Duration::from_secs(2), async {
+            let mut events = Vec::new();
+            let mut failed = false;
+            loop {
+                let event = rx.recv().await?;
+                let done = failed && matches!(event, LiveEvent::Built { .. });
+                failed |= matches!(event, LiveEvent::Error(_));
+                events.push(event);
+                if done {
+                    return Ok::<_, broadcast::error::RecvError>(events);
+                }
+            }
+        })
+        .await
+        .expect("Timeout waiting for the asset error")?;
+
+        assert!(events.iter().all(|event| match event {
+            LiveEvent::Changed(changes) => changes
+                .iter()
+                .all(|change| !change.path.ends_with("logo.svg")),
+            _ => true,
+        }));
+        let batch_start = events
+            .iter()
+            .rposition(|event| *event == LiveEvent::Building)
+            .unwrap();
+        let batch = &events[batch_start..];
+        let Some(LiveEvent::Error(error)) = batch.get(1) else {
+            panic!("Expected the error first, got {:?}", batch);
+        };
+        assert_eq!(error.file, "logo.svg");
+        assert_eq!(error.url, None);
+
+        Ok(())
+    }
+
     #[test]
-    fn test_is_relevant_event() {
-        use notify::event::{AccessKind, CreateKind, ModifyKind, RemoveKind};
-
-        // Test create events
-        assert!(is_relevant_event(&Event::new(EventKind::Create(
-            CreateKind::File
-        ))));
-        assert!(!is_relevant_event(&Event::new(EventKind::Create(
-            CreateKind::Folder
-        ))));
-
-        // Test modify events
-        assert!(is_relevant_event(&Event::new(EventKind::Modify(
-            ModifyKind::Data(notify::event::DataChange::Content)
-        ))));
-        assert!(is_relevant_event(&Event::new(EventKind::Modify(
-            ModifyKind::Name(notify::event::RenameMode::From)
-        ))));
-
-        // Test remove events
-        assert!(is_relevant_event(&Event::new(EventKind::Remove(
-            RemoveKind::File
-        ))));
-        assert!(!is_relevant_event(&Event::new(EventKind::Remove(
-            RemoveKind::Folder
-        ))));
-
-        // Test irrelevant events
-        assert!(!is_relevant_event(&Event::new(EventKind::Access(
-            AccessKind::Read
-        ))));
+    fn test_settled_paths() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path().join("guide");
+        fs::create_dir_all(&dir)?;
+        let page = temp_dir.path().join("page.md");
+        let removed = temp_dir.path().join("removed.md");
+        let writing = temp_dir.path().join("writing.md");
+
+        let event = |path: &Path, kind| DebouncedEvent {
+            path: path.to_path_buf(),
+            kind,
+        };
+        let paths = settled_paths(vec![
+            event(&removed, DebouncedEventKind::Any),
+            event(&page, DebouncedEventKind::Any),
+            event(&dir, DebouncedEventKind::Any),
+            event(&writing, DebouncedEventKind::AnyContinuous),
+            event(&page, DebouncedEventKind::Any),
+        ]);
+
+        // Directories and files still being written are skipped
+        assert_eq!(paths, [page, removed]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watcher_coalesces_changes() -> Result<()> {
+        let (_temp_dir, content_dir, output_dir) = test_dirs()?;
+        fs::write(content_dir.join("page.md"), "# Page")?;
+
+        let mut rx = start_watcher_with(
+            &content_dir,
+            &output_dir,
+            Templates::default(),
+            Duration::from_millis(200),
+        )?;
+
+        // Saving several times in quick succession renders the page once
+        let page = content_dir.join("page.md");
+        for content in ["# Page", "# Page\n\nFirst", "# Page\n\nSecond"] {
+            fs::write(&page, content)?;
+            sleep(Duration::from_millis(20)).await;
+        }
+        let mut changes = Vec::new();
+        let mut rendered = 0;
+        tokio::select! {
+            _ = sleep(Duration::from_secs(1)) => {}
+            _ = async {
+                while let Ok(event) = rx.recv().await {
+                    match event {
+                        LiveEvent::Changed(batch) => changes.extend(batch),
+                        LiveEvent::Built { rendered: count, .. } => rendered += count,
+                        _ => {}
+                    }
+                }
+            } => {}
+        }
+
+        let page = page.canonicalize()?;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, page);
+        assert_eq!(rendered, 1);
+        let html = fs::read_to_string(output_dir.join("page.html"))?;
+        assert!(html.contains("Second"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watcher_reloads_templates() -> Result<()> {
+        let (temp_dir, content_dir, output_dir) = test_dirs()?;
+        let templates_dir = temp_dir.path().join("templates");
+
+        fs::create_dir_all(&templates_dir)?;
+        fs::write(content_dir.join("page.md"), "# Page")?;
+        let template = templates_dir.join("page.html");
+        fs::write(&template, "<main>{{ content }}</main>")?;
+
+        let mut rx = start_watcher_with(
+            &content_dir,
+            &output_dir,
+            Templates::load(Some(&templates_dir), &toml::Table::new())?,
+            DEBOUNCE,
+        )?;
+
+        // Editing a template re-renders every page with it
+        fs::write(&template, "<article>{{ content }}</article>")?;
+        let page = output_dir.join("page.html");
+        wait_for(&mut rx, "page render", || {
+            fs::read_to_string(&page).is_ok_and(|html| html.starts_with("<article>"))
+        })
+        .await;
+
+        // A broken template is reported and the previous one kept
+        fs::write(&template, "{% if %}")?;
+        let error = tokio::select! {
+            _ = sleep(Duration::from_secs(2)) => {
+                panic!("Timeout waiting for template error");
+            }
+            error = async {
+                loop {
+                    if let Ok(LiveEvent::Error(error)) = rx.recv().await {
+                        break error;
+                    }
+                }
+            } => error
+        };
+        assert_eq!(error.file, "page.html");
+        assert!(fs::read_to_string(&page)?.starts_with("<article>"));
+
+        Ok(())
     }
 }
@@ -115,15 +115,15 @@ async fn test_file_watching_and_live_reload() -> Result<()> {
 
     // Connect to SSE endpoint
     let client = Client::new();
-    let events_response = client.get(format!("{}/events", server_url)).send().await?;
+    let mut events_response = client.get(format!("{}/events", server_url)).send().await?;
 
     assert!(events_response.status().is_success());
 
     // Modify the file
     create_markdown_file(&test_file, "# Updated Content")?;
 
-    // Wait for file system event to be processed
-    sleep(Duration::from_millis(100)).await;
+    // Wait for the change to settle and the site to be rebuilt
+    wait_for_event(&mut events_response, "\"state\":\"done\"").await?;
 
     // Verify content was updated
     let response = client
@@ -284,7 +284,7 @@ async fn test_index_generation() -> Result<()> {
     assert!(body.contains("Page 1"));
     assert!(body.contains("Page 2"));
     assert!(body.contains("Page 3"));
-    assert!(body.contains("in docs")); // Check directory indication
+    assert!(body.contains("<summary>Docs</summary>")); // Check directory indication
     assert!(body.contains("href=\"page1.html\"")); // Check links
     assert!(body.contains("href=\"docs/page3.html\"")); // Check nested links
 
@@ -292,3 +292,439 @@ async fn test_index_generation() -> Result<()> {
     server_handle.abort();
     Ok(())
 }
+
+#[test]
+fn test_build_command() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let content_dir = temp_dir.path().join("content");
+    let output_dir = temp_dir.path().join("output");
+
+    create_markdown_file(&content_dir.join("page.md"), "# Static Page")?;
+
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_live-md"))
+        .arg("build")
+        .arg("--content-dir")
+        .arg(&content_dir)
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .status()?;
+    assert!(status.success());
+
+    let page = fs::read_to_string(output_dir.join("page.html"))?;
+    assert!(page.contains("Static Page"));
+    assert!(!page.contains("EventSource"));
+    let index = fs::read_to_string(output_dir.join("index.html"))?;
+    assert!(!index.contains("EventSource"));
+
+    // A file that fails to render makes the build fail
+    fs::write(content_dir.join("broken.md"), [0xff, 0xfe])?;
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_live-md"))
+        .arg("build")
+        .arg("--content-dir")
+        .arg(&content_dir)
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .status()?;
+    assert!(!status.success());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_readme_landing_page() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let config = create_test_config(&temp_dir)?;
+
+    fs::create_dir_all(&config.content_dir)?;
+    fs::create_dir_all(&config.output_dir)?;
+
+    create_markdown_file(&config.content_dir.join("README.md"), "# Welcome")?;
+    create_markdown_file(&config.content_dir.join("notes.md"), "# Notes")?;
+
+    let (server_handle, server_url) = start_test_server(config).await?;
+    let client = Client::new();
+
+    // The README is the landing page
+    let body = client
+        .get(format!("{}/", server_url))
+        .send()
+        .await?
+        .text()
+        .await?;
+    assert!(body.contains("Welcome"));
+    assert!(!body.contains("Documentation Index"));
+
+    // The generated listing is still available
+    let response = client.get(format!("{}/_index", server_url)).send().await?;
+    assert!(response.status().is_success());
+    let body = response.text().await?;
+    assert!(body.contains("Documentation Index"));
+    assert!(body.contains("href=\"notes.html\""));
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_relative_markdown_links() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let config = create_test_config(&temp_dir)?;
+
+    create_markdown_file(
+        &config.content_dir.join("guide").join("intro.md"),
+        "# Intro\n\nSee [setup](../setup.md#install).",
+    )?;
+    create_markdown_file(&config.content_dir.join("setup.md"), "# Setup")?;
+    fs::create_dir_all(&config.output_dir)?;
+
+    let (server_handle, server_url) = start_test_server(config).await?;
+
+    let client = Client::new();
+    let body = client
+        .get(format!("{}/guide/intro.html", server_url))
+        .send()
+        .await?
+        .text()
+        .await?;
+    assert!(body.contains("href=\"../setup.html#install\""));
+
+    // The rewritten link resolves to a served page
+    let response = client
+        .get(format!("{}/guide/../setup.html", server_url))
+        .send()
+        .await?;
+    assert!(response.status().is_success());
+    assert!(response.text().await?.contains("Setup"));
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_assets_are_served() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let config = create_test_config(&temp_dir)?;
+
+    create_markdown_file(
+        &config.content_dir.join("page.md"),
+        "# Page\n\n![logo](images/logo.svg)",
+    )?;
+    create_markdown_file(
+        &config.content_dir.join("images").join("logo.svg"),
+        "<svg xmlns=\"http://www.w3.org/2000/svg\"/>",
+    )?;
+    fs::create_dir_all(&config.output_dir)?;
+
+    let (server_handle, server_url) = start_test_server(config.clone()).await?;
+
+    let client = Client::new();
+    let response = client
+        .get(format!("{}/images/logo.svg", server_url))
+        .send()
+        .await?;
+    assert!(response.status().is_success());
+    assert!(response.text().await?.contains("<svg"));
+
+    // Assets added while the server runs are picked up by the watcher
+    create_markdown_file(&config.content_dir.join("style.css"), "body {}")?;
+    let mut found = false;
+    for _ in 0..20 {
+        sleep(Duration::from_millis(100)).await;
+        let response = client
+            .get(format!("{}/style.css", server_url))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            found = true;
+            break;
+        }
+    }
+    assert!(found);
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_in_memory_serving() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let mut config = create_test_config(&temp_dir)?;
+    config.in_memory = true;
+
+    create_markdown_file(&config.content_dir.join("page.md"), "# First Version")?;
+    create_markdown_file(
+        &config.content_dir.join("guide").join("README.md"),
+        "# Guide",
+    )?;
+    create_markdown_file(&config.content_dir.join("style.css"), "body {}")?;
+
+    let (server_handle, server_url) = start_test_server(config.clone()).await?;
+    let client = Client::new();
+
+    let body = client
+        .get(format!("{}/page.html", server_url))
+        .send()
+        .await?
+        .text()
+        .await?;
+    assert!(body.contains("First Version"));
+
+    let body = client
+        .get(format!("{}/guide/", server_url))
+        .send()
+        .await?
+        .text()
+        .await?;
+    assert!(body.contains("Guide"));
+
+    // Directories without a trailing slash are redirected, as when serving from disk
+    let response = Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?
+        .get(format!("{}/guide?theme=dark", server_url))
+        .send()
+        .await?;
+    assert_eq!(response.status(), reqwest::StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(response.headers()["location"], "/guide/?theme=dark");
+    let body = client
+        .get(format!("{}/guide", server_url))
+        .send()
+        .await?
+        .text()
+        .await?;
+    assert!(body.contains("Guide"));
+
+    let response = client
+        .get(format!("{}/style.css", server_url))
+        .send()
+        .await?;
+    assert!(response.status().is_success());
+    assert_eq!(response.text().await?, "body {}");
+
+    // Markdown sources and missing pages aren't served
+    for path in ["page.md", "missing.html"] {
+        let response = client
+            .get(format!("{}/{}", server_url, path))
+            .send()
+            .await?;
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+
+    // Edits are rendered on the next request
+    create_markdown_file(&config.content_dir.join("page.md"), "# Second Version")?;
+    let body = client
+        .get(format!("{}/page.html", server_url))
+        .send()
+        .await?
+        .text()
+        .await?;
+    assert!(body.contains("Second Version"));
+
+    assert!(!config.output_dir.exists());
+
+    server_handle.abort();
+    Ok(())
+}
+
+/// Reads server-sent events until one has a data line containing `needle`
+async fn wait_for_event(response: &mut reqwest::Response, needle: &str) -> Result<String> {
+    let mut buffer = String::new();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while let Some(chunk) = response.chunk().await? {
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
+            if let Some(line) = buffer
+                .lines()
+                .find(|line| line.starts_with("data:") && line.contains(needle))
+            {
+                return Ok(line.trim_start_matches("data:").trim().to_string());
+            }
+        }
+        Err(anyhow::anyhow!("Event stream ended"))
+    })
+    .await?
+}
+
+#[tokio::test]
+async fn test_live_reload_patches_changed_page() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let config = create_test_config(&temp_dir)?;
+
+    let test_file = config.content_dir.join("guide").join("spec.md");
+    create_markdown_file(&test_file, "# Spec\n\nFirst draft")?;
+    fs::create_dir_all(&config.output_dir)?;
+
+    let (server_handle, server_url) = start_test_server(config).await?;
+
+    let client = Client::new();
+    let mut events = client
+        .get(format!("{}/events?page=guide/spec.html", server_url))
+        .send()
+        .await?;
+    assert!(events.status().is_success());
+
+    create_markdown_file(&test_file, "# Spec\n\nSecond draft")?;
+    let data = wait_for_event(&mut events, "Second draft").await?;
+
+    // The page's new body is pushed so the browser can patch it in place
+    let message: serde_json::Value = serde_json::from_str(&data)?;
+    assert_eq!(message["type"], "patch");
+    assert_eq!(message["url"], "guide/spec.html");
+    let body = message["body"].as_str().unwrap();
+    assert!(body.contains("<p>Second draft</p>"));
+    assert!(body.contains("class=\"site-nav\""));
+    assert!(!body.contains("<body"));
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_live_reload_targets_affected_pages() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let config = create_test_config(&temp_dir)?;
+
+    let one = config.content_dir.join("one.md");
+    let two = config.content_dir.join("two.md");
+    create_markdown_file(&one, "# One\n\nOriginal")?;
+    create_markdown_file(&two, "# Two\n\nOriginal")?;
+    fs::create_dir_all(&config.output_dir)?;
+
+    let (server_handle, server_url) = start_test_server(config.clone()).await?;
+
+    let client = Client::new();
+    let subscribe = |page: &str| {
+        client
+            .get(format!("{}/events?page={}", server_url, page))
+            .send()
+    };
+    let mut one_events = subscribe("one.html").await?;
+    let mut two_events = subscribe("two.html").await?;
+
+    // Editing one page only notifies browsers showing it
+    create_markdown_file(&one, "# One\n\nEdited")?;
+    let data = wait_for_event(&mut one_events, "\"url\"").await?;
+    assert!(data.contains("\"url\":\"one.html\""));
+
+    create_markdown_file(&two, "# Two\n\nEdited")?;
+    let data = wait_for_event(&mut two_events, "\"url\"").await?;
+    assert!(data.contains("\"url\":\"two.html\""), "{}", data);
+
+    // A new page changes every page's navigation
+    create_markdown_file(&config.content_dir.join("three.md"), "# Three")?;
+    wait_for_event(&mut one_events, "three.html").await?;
+    wait_for_event(&mut two_events, "three.html").await?;
+
+    server_handle.abort();
+    Ok(())
+}
+
+/// Reads WebSocket messages until one other than a build status arrives
+async fn next_message(
+    socket: &mut tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >,
+) -> Result<serde_json::Value> {
+    use futures_util::StreamExt;
+    use tokio_tungstenite::tungstenite::Message;
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while let Some(message) = socket.next().await {
+            if let Message::Text(text) = message? {
+                let message: serde_json::Value = serde_json::from_str(&text)?;
+                if message["type"] != "build-status" {
+                    return Ok(message);
+                }
+            }
+        }
+        Err(anyhow::anyhow!("WebSocket closed"))
+    })
+    .await?
+}
+
+#[tokio::test]
+async fn test_websocket_live_reload() -> Result<()> {
+    use futures_util::SinkExt;
+    use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+    let temp_dir = TempDir::new()?;
+    let config = create_test_config(&temp_dir)?;
+
+    let one = config.content_dir.join("one.md");
+    let two = config.content_dir.join("two.md");
+    create_markdown_file(&one, "# One\n\nOriginal")?;
+    create_markdown_file(&two, "# Two\n\nOriginal")?;
+    fs::create_dir_all(&config.output_dir)?;
+
+    let (server_handle, server_url) = start_test_server(config).await?;
+
+    let ws_url = format!("{}/ws", server_url.replacen("http", "ws", 1));
+    let (mut socket, _) = connect_async(ws_url).await?;
+    socket
+        .send(Message::Text(
+            r#"{"type":"visit","page":"one.html","scroll":120}"#.into(),
+        ))
+        .await?;
+    sleep(Duration::from_millis(100)).await;
+
+    // Changes to other pages aren't sent, this page's are patched in
+    create_markdown_file(&two, "# Two\n\nEdited")?;
+    sleep(Duration::from_millis(300)).await;
+    create_markdown_file(&one, "# One\n\nEdited")?;
+    let message = next_message(&mut socket).await?;
+    assert_eq!(message["type"], "patch");
+    assert_eq!(message["url"], "one.html");
+    assert!(message["body"].as_str().unwrap().contains("Edited"));
+
+    // Reloads bring the page back to where the browser reported it was
+    fs::remove_file(&two)?;
+    let message = next_message(&mut socket).await?;
+    assert_eq!(message["type"], "reload");
+    assert_eq!(message["url"], "two.html");
+    assert_eq!(message["scroll"], 120);
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_render_errors_are_sent_to_the_browser() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let config = create_test_config(&temp_dir)?;
+
+    let page = config.content_dir.join("page.md");
+    create_markdown_file(&page, "# Page")?;
+    fs::create_dir_all(&config.output_dir)?;
+
+    let (server_handle, server_url) = start_test_server(config.clone()).await?;
+
+    let client = Client::new();
+    let mut events = client
+        .get(format!("{}/events?page=page.html", server_url))
+        .send()
+        .await?;
+
+    create_markdown_file(&page, "---\nweight: heavy\n---\n# Page")?;
+    let data = wait_for_event(&mut events, "\"type\":\"error\"").await?;
+    let message: serde_json::Value = serde_json::from_str(&data)?;
+    assert_eq!(message["file"], "page.md");
+    assert_eq!(message["url"], "page.html");
+    assert!(message["message"]
+        .as_str()
+        .unwrap()
+        .contains("Failed to parse front matter"));
+    assert!(!message["context"].as_array().unwrap().is_empty());
+
+    // The last good render is still served
+    let response = client
+        .get(format!("{}/page.html", server_url))
+        .send()
+        .await?;
+    assert!(response.status().is_success());
+
+    // Fixing the file patches the page, which clears the error
+    create_markdown_file(&page, "# Fixed")?;
+    wait_for_event(&mut events, "\"type\":\"patch\"").await?;
+
+    server_handle.abort();
+    Ok(())
+}